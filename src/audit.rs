@@ -0,0 +1,32 @@
+use std::{io, path::Path};
+
+use serde_json::json;
+use spider_client::{message::UiInput, Relation};
+
+use crate::{event_log, relation, rotate::RotatingFile};
+
+/// Appends every UI input to a JSONL file, one object per line:
+/// `{"at_ms": .., "element": .., "viewer": .., "input": ..}`. `viewer` is
+/// null for input that did not say which viewer it came from.
+pub struct AuditLog {
+    file: RotatingFile,
+}
+
+impl AuditLog {
+    pub fn open(path: &Path) -> Result<Self, io::Error> {
+        let file = RotatingFile::open(path, None)?;
+        Ok(Self { file })
+    }
+
+    pub fn record(&mut self, element_id: &str, viewer: Option<&Relation>, input: &UiInput) {
+        let line = json!({
+            "at_ms": event_log::now_ms(),
+            "element": element_id,
+            "viewer": viewer.map(relation::display),
+            "input": input,
+        });
+        if let Err(e) = self.file.write_line(&line.to_string()) {
+            log!(Error; "Failed to write audit log: {}", e);
+        }
+    }
+}
@@ -0,0 +1,68 @@
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+#[cfg(test)]
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// Where `State` reads the current time, so time-dependent behaviour such
+/// as message expiry and idle clearing can be driven by hand.
+pub trait Clock: Send {
+    fn now(&self) -> Instant;
+    /// Seconds since the epoch.
+    fn unix_secs(&self) -> u64;
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn unix_secs(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default()
+    }
+}
+
+/// A clock that stands still until advanced. Clones share the same time.
+#[cfg(test)]
+#[derive(Clone)]
+pub struct ManualClock {
+    start: Instant,
+    unix_start: u64,
+    elapsed: Arc<Mutex<Duration>>,
+}
+
+#[cfg(test)]
+impl ManualClock {
+    pub fn new(unix_start: u64) -> Self {
+        Self {
+            start: Instant::now(),
+            unix_start,
+            elapsed: Arc::default(),
+        }
+    }
+
+    pub fn advance(&self, by: Duration) {
+        *self.elapsed.lock().expect("clock lock poisoned") += by;
+    }
+
+    fn elapsed(&self) -> Duration {
+        *self.elapsed.lock().expect("clock lock poisoned")
+    }
+}
+
+#[cfg(test)]
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        self.start + self.elapsed()
+    }
+
+    fn unix_secs(&self) -> u64 {
+        self.unix_start + self.elapsed().as_secs()
+    }
+}
@@ -0,0 +1,37 @@
+use std::time::Duration;
+
+use spider_client::message::DatasetData;
+
+use crate::messages;
+
+/// Render Messages entries with runs from one sender merged into a block.
+///
+/// An entry joins the block above it when it has the same sender and
+/// arrived within `window` of the block's previous entry. Each block is a
+/// header naming the sender followed by one indented line per message.
+pub fn render(msgs: &[DatasetData], window: Duration) -> String {
+    let mut blocks: Vec<String> = vec![];
+    let mut last: Option<(&str, u64)> = None;
+    for entry in msgs {
+        let sender = messages::field(entry, "sender").unwrap_or("unknown");
+        let at = messages::field(entry, "received_at")
+            .and_then(|at| at.parse().ok())
+            .unwrap_or_default();
+        let joins = match last {
+            Some((last_sender, last_at)) => {
+                last_sender == sender && at.saturating_sub(last_at) <= window.as_secs()
+            }
+            None => false,
+        };
+        let line = format!("  {}", messages::text(entry));
+        match blocks.last_mut() {
+            Some(block) if joins => {
+                block.push('\n');
+                block.push_str(&line);
+            }
+            _ => blocks.push(format!("{}:\n{}", sender, line)),
+        }
+        last = Some((sender, at));
+    }
+    blocks.join("\n")
+}
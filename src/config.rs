@@ -0,0 +1,891 @@
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    net::{IpAddr, Ipv4Addr},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use spider_client::Relation;
+
+use crate::{
+    encoding::Encoding,
+    faults::FaultRates,
+    output::ColorMode,
+    persist::PersistFormat,
+    relation,
+    rotate::Rotation,
+    sanitize::SanitizePolicy,
+    schema::{Schema, SchemaMode},
+    stress::StressProfile,
+    timefmt::TimeFormat,
+    transform::Pipeline,
+};
+
+/// How each send picks its recipients from the Recp dataset.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum SendMode {
+    /// Every send goes to every recipient.
+    Broadcast,
+    /// Each send goes to the next recipient in turn.
+    RoundRobin,
+}
+
+/// Who a send goes to when the Recp list has no active recipients.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum NoRecpBehavior {
+    /// Refuse the send.
+    Reject,
+    /// Send to every identity in the directory.
+    Broadcast,
+    /// Send to the CC peers only.
+    Cc,
+}
+
+/// What the process does, chosen by the first argument.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// Serve the test page and take commands. The default.
+    Run,
+    /// Send one message and exit.
+    Send,
+    /// Print received events without building a page.
+    Monitor,
+    /// Send a ramping load and report how the router kept up.
+    Stress,
+    /// Print each received message on one line, like `tail -f`.
+    Tail,
+}
+
+impl Mode {
+    fn name(self) -> &'static str {
+        match self {
+            Mode::Run => "run",
+            Mode::Send => "send",
+            Mode::Monitor => "monitor",
+            Mode::Stress => "stress",
+            Mode::Tail => "tail",
+        }
+    }
+}
+
+/// Runtime options for the test router, read from the command line.
+#[derive(Clone)]
+pub struct Config {
+    pub mode: Mode,
+    /// Senders whose events are accepted. Empty accepts everyone.
+    pub allowlist: Vec<Relation>,
+    /// Peers copied on every send, in addition to the Recp dataset.
+    pub cc: Vec<Relation>,
+    /// File the approval code is written to while approval is pending.
+    pub approval_code_path: PathBuf,
+    /// How long a send must go without an error before it counts as sent.
+    pub ack_window: Duration,
+    /// How many times a failed send is retried automatically.
+    pub max_retries: u32,
+    /// Wait before the first retry, doubled for each retry after.
+    pub retry_backoff: Duration,
+    /// Title of the page shown in viewers.
+    pub page_title: String,
+    /// Optional text shown at the top of the page.
+    pub page_description: Option<String>,
+    pub send_mode: SendMode,
+    pub no_recp_behavior: NoRecpBehavior,
+    /// Resubscribe if no dataset or event messages arrive for this long.
+    pub watchdog: Option<Duration>,
+    /// How each message row is displayed, e.g. `"[{timestamp}] {sender}: {text}"`.
+    pub msg_template: String,
+    /// Delete the client state file when the router denies this client.
+    pub reset_on_denied: bool,
+    /// Also delete the keyfile when resetting on denial.
+    pub reset_keyfile: bool,
+    /// JSONL file every outbound event is recorded to.
+    pub event_log: Option<PathBuf>,
+    /// Rotate the event log once it grows past this many bytes.
+    pub log_max_size: Option<u64>,
+    /// How many rotated event logs are kept.
+    pub log_keep: usize,
+    /// Recorded event log to re-send after connecting.
+    pub replay: Option<PathBuf>,
+    /// Replay as fast as possible instead of at the recorded timing.
+    pub replay_fast: bool,
+    /// Recipients read from `--recp-file`.
+    pub file_recps: Vec<Relation>,
+    /// The load profile of the `stress` subcommand.
+    pub stress: StressProfile,
+    /// Send this text once and exit instead of running interactively.
+    pub once: Option<String>,
+    /// In `--once` mode, count the send as delivered only once every
+    /// recipient has sent a receipt within the ack window.
+    pub once_wait: bool,
+    /// Largest dataset kept in memory; bigger updates are truncated.
+    pub max_dataset_len: usize,
+    /// Log, count and record events other than `test_event`.
+    pub log_unknown_events: bool,
+    /// How control characters in typed input are handled.
+    pub sanitize: SanitizePolicy,
+    /// Router address used when no client state exists yet.
+    pub router_addr: String,
+    /// Second router whose connection receives events bridged from the first.
+    pub bridge_addr: Option<String>,
+    /// Also bridge events from the second connection back to the first.
+    pub bridge_both: bool,
+    /// Identity name published to the directory and sent in whoami replies.
+    pub name: String,
+    /// How many recent message ids are remembered for deduplication.
+    pub dedup_window: usize,
+    /// Text added before and after every sent message.
+    pub msg_prefix: String,
+    pub msg_suffix: String,
+    /// How file contents are encoded for sending.
+    pub encoding: Encoding,
+    /// Where received files are written.
+    pub download_dir: PathBuf,
+    /// How many messages the Messages dataset is trimmed to.
+    pub max_msgs: usize,
+    /// Wait this long after connecting before sending the page.
+    pub page_delay: Option<Duration>,
+    /// Event name patterns subscribed at startup, e.g. `test_*`.
+    pub event_patterns: Vec<String>,
+    /// Add the sender of every received message to the Recp list.
+    pub auto_add_senders: bool,
+    /// Identity properties published alongside the name, from `--prop key=value`.
+    pub identity_props: BTreeMap<String, String>,
+    /// How many raw received messages are kept for Dump Events.
+    pub ring_size: usize,
+    /// How many viewers get their own filter before further viewers are
+    /// ignored.
+    pub max_viewers: usize,
+    /// Hold received messages this long and append them as one entry.
+    pub batch_window: Option<Duration>,
+    /// Transforms applied to the text of outgoing and received messages.
+    pub transform_out: Pipeline,
+    pub transform_in: Pipeline,
+    /// Drop, corrupt or delay outgoing sends at these rates.
+    pub inject_faults: Option<FaultRates>,
+    /// How long a send is held back by an injected delay.
+    pub fault_delay: Duration,
+    /// Reply to each received message with this template, where `{text}`
+    /// is the received text and `{sender}` the sender's relation.
+    pub auto_respond: Option<String>,
+    /// Serve the page defined in this JSON file instead of the built-in one.
+    pub page_file: Option<PathBuf>,
+    /// Record every UI input to this JSONL file.
+    pub audit_log: Option<PathBuf>,
+    /// Store each sender's test events in `seq` order, waiting at most this
+    /// long for a missing one.
+    pub in_order: Option<Duration>,
+    /// File Dump Events writes to.
+    pub dump_path: PathBuf,
+    /// Sign outgoing messages and verify signatures on received ones.
+    pub sign: bool,
+    /// Where the signing key is kept under `--sign`.
+    pub sign_key_path: PathBuf,
+    /// Reconnect to the router when the connection closes instead of exiting.
+    pub reconnect: bool,
+    /// Send a `reconnected` event to every recipient after reconnecting.
+    pub announce_reconnect: bool,
+    /// Directory the Recp and Messages datasets are cached in.
+    pub persist_dir: Option<PathBuf>,
+    pub persist_format: PersistFormat,
+    /// Passphrase the cache files are encrypted with, from
+    /// `--persist-passphrase` or the `TEST_ROUTER_PASSPHRASE` variable.
+    pub persist_passphrase: Option<String>,
+    /// Hold typed messages for confirmation, showing who they would go to.
+    pub confirm_sends: bool,
+    /// Queue typed messages and send at most this many per second.
+    pub send_rate: Option<usize>,
+    /// Clear the Messages dataset once no event has arrived for this long.
+    pub auto_clear_idle: Option<Duration>,
+    /// How long Test All waits for recipients to answer.
+    pub ping_timeout: Duration,
+    /// How many pings a latency measurement sends by default.
+    pub latency_count: usize,
+    /// After a denial, wait this long and connect again to restart approval.
+    pub reapprove_on_denied: Option<Duration>,
+    /// After a denial, wait at least this long before any reconnect.
+    pub denied_cooldown: Option<Duration>,
+    /// Print received events to stdout as JSON lines, logging to stderr.
+    pub output_json: bool,
+    /// Key of map payloads shown as the message text when they lack `text`.
+    pub text_field: Option<String>,
+    /// Show messages as blocks, merging a sender's messages that arrive
+    /// within this window of each other.
+    pub compact_window: Option<Duration>,
+    /// Serve an HTML mirror of the recipients and messages on this port.
+    pub web_port: Option<u16>,
+    /// Address the web mirror listens on. Loopback unless widened with
+    /// `--web-bind`, since the page shows message contents.
+    pub web_bind: IpAddr,
+    /// Schema received test events are checked against before being stored.
+    pub schema: Option<Schema>,
+    /// What to do with test events that do not match `schema`.
+    pub schema_mode: SchemaMode,
+    /// Refresh the Directory element at most once per this window.
+    pub directory_window: Option<Duration>,
+    /// Probe the router this often and reconnect if a probe goes unanswered.
+    pub health_check: Option<Duration>,
+    pub color: ColorMode,
+    /// Prune recipients missing from the directory this often.
+    pub auto_prune: Option<Duration>,
+    /// Ping every recipient this often and show who answered in Recp Stats.
+    pub recp_check: Option<Duration>,
+    /// Format of the `timestamp` field of Messages entries.
+    pub time_format: TimeFormat,
+    /// Directory recipient presets are saved in.
+    pub preset_dir: PathBuf,
+    /// Forward `SendEvent` requests received from the router.
+    pub relay: bool,
+    /// Re-send the page this long after a viewer clears it.
+    pub restore_page: Option<Duration>,
+    /// Hold further sends while this many are waiting for their outcome.
+    pub max_in_flight: Option<usize>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            mode: Mode::Run,
+            allowlist: vec![],
+            cc: vec![],
+            approval_code_path: PathBuf::from("approval_code.txt"),
+            ack_window: Duration::from_secs(2),
+            max_retries: 0,
+            retry_backoff: Duration::from_secs(1),
+            page_title: String::from("Router Test Page"),
+            page_description: None,
+            send_mode: SendMode::Broadcast,
+            no_recp_behavior: NoRecpBehavior::Cc,
+            watchdog: None,
+            msg_template: String::from("{text}"),
+            reset_on_denied: false,
+            reset_keyfile: false,
+            event_log: None,
+            log_max_size: None,
+            log_keep: 5,
+            replay: None,
+            replay_fast: false,
+            file_recps: vec![],
+            stress: StressProfile::default(),
+            once: None,
+            once_wait: false,
+            max_dataset_len: 1000,
+            log_unknown_events: false,
+            sanitize: SanitizePolicy::Strip,
+            router_addr: String::from("localhost:1930"),
+            bridge_addr: None,
+            bridge_both: false,
+            name: String::from("Test Router"),
+            dedup_window: 256,
+            msg_prefix: String::new(),
+            msg_suffix: String::new(),
+            encoding: Encoding::Base64,
+            download_dir: PathBuf::from("received_files"),
+            max_msgs: 10,
+            page_delay: None,
+            event_patterns: vec![],
+            auto_add_senders: false,
+            identity_props: BTreeMap::new(),
+            ring_size: 200,
+            max_viewers: 8,
+            batch_window: None,
+            transform_out: Pipeline::default(),
+            transform_in: Pipeline::default(),
+            inject_faults: None,
+            fault_delay: Duration::from_secs(2),
+            auto_respond: None,
+            page_file: None,
+            audit_log: None,
+            in_order: None,
+            dump_path: PathBuf::from("event_dump.txt"),
+            sign: false,
+            sign_key_path: PathBuf::from("sign_key.dat"),
+            reconnect: false,
+            announce_reconnect: false,
+            persist_dir: None,
+            persist_format: PersistFormat::Json,
+            persist_passphrase: std::env::var("TEST_ROUTER_PASSPHRASE").ok(),
+            confirm_sends: false,
+            send_rate: None,
+            auto_clear_idle: None,
+            ping_timeout: Duration::from_secs(5),
+            latency_count: 10,
+            reapprove_on_denied: None,
+            denied_cooldown: None,
+            output_json: false,
+            text_field: None,
+            compact_window: None,
+            web_port: None,
+            web_bind: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            schema: None,
+            schema_mode: SchemaMode::Flag,
+            directory_window: None,
+            health_check: None,
+            color: ColorMode::Auto,
+            auto_prune: None,
+            recp_check: None,
+            time_format: TimeFormat::EpochSecs,
+            preset_dir: PathBuf::from("presets"),
+            relay: false,
+            restore_page: None,
+            max_in_flight: None,
+        }
+    }
+}
+
+impl Config {
+    pub fn from_args() -> Result<Self, io::Error> {
+        Self::parse(std::env::args().skip(1))
+    }
+
+    /// Parse the arguments after the program name: an optional subcommand,
+    /// then flags, each checked against the modes it applies to.
+    fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, io::Error> {
+        let mut config = Self::default();
+        let mut args = args.into_iter().peekable();
+        match args.peek().map(String::as_str) {
+            Some("run") => {
+                args.next();
+            }
+            Some("send") => {
+                args.next();
+                config.mode = Mode::Send;
+                let text = args
+                    .next()
+                    .ok_or_else(|| invalid_input(String::from("send requires a message")))?;
+                config.once = Some(text);
+            }
+            Some("monitor") => {
+                args.next();
+                config.mode = Mode::Monitor;
+            }
+            Some("stress") => {
+                args.next();
+                config.mode = Mode::Stress;
+            }
+            Some("tail") => {
+                args.next();
+                config.mode = Mode::Tail;
+            }
+            _ => {}
+        }
+        let mut flags = vec![];
+        while let Some(arg) = args.next() {
+            flags.push(arg.clone());
+            match arg.as_str() {
+                "--allow" => {
+                    let value = next_value(&mut args, &arg)?;
+                    config.allowlist.push(parse_relation(&value)?);
+                }
+                "--allowlist" => {
+                    let value = next_value(&mut args, &arg)?;
+                    config
+                        .allowlist
+                        .extend(read_relations(&PathBuf::from(value))?);
+                }
+                "--cc" => {
+                    let value = next_value(&mut args, &arg)?;
+                    for relation in value.split(',').filter(|r| !r.is_empty()) {
+                        config.cc.push(parse_relation(relation)?);
+                    }
+                }
+                "--approval-code-file" => {
+                    config.approval_code_path = PathBuf::from(next_value(&mut args, &arg)?);
+                }
+                "--ack-window" => {
+                    config.ack_window = parse_duration(&next_value(&mut args, &arg)?)?;
+                }
+                "--title" => {
+                    config.page_title = next_value(&mut args, &arg)?;
+                }
+                "--description" => {
+                    config.page_description = Some(next_value(&mut args, &arg)?);
+                }
+                "--send-mode" => {
+                    config.send_mode = match next_value(&mut args, &arg)?.as_str() {
+                        "broadcast" => SendMode::Broadcast,
+                        "round-robin" => SendMode::RoundRobin,
+                        other => {
+                            return Err(invalid_input(format!("invalid send mode: {}", other)))
+                        }
+                    };
+                }
+                "--no-recp-behavior" => {
+                    config.no_recp_behavior = match next_value(&mut args, &arg)?.as_str() {
+                        "reject" => NoRecpBehavior::Reject,
+                        "broadcast" => NoRecpBehavior::Broadcast,
+                        "cc" => NoRecpBehavior::Cc,
+                        other => {
+                            return Err(invalid_input(format!(
+                                "invalid no-recipient behavior: {}",
+                                other
+                            )))
+                        }
+                    };
+                }
+                "--watchdog" => {
+                    config.watchdog = Some(parse_duration(&next_value(&mut args, &arg)?)?);
+                }
+                "--msg-template" => {
+                    config.msg_template = next_value(&mut args, &arg)?;
+                }
+                "--reset-on-denied" => config.reset_on_denied = true,
+                "--reset-keyfile" => config.reset_keyfile = true,
+                "--color" => {
+                    let value = next_value(&mut args, &arg)?;
+                    config.color = ColorMode::from_tag(&value)
+                        .ok_or_else(|| invalid_input(format!("invalid color mode: {}", value)))?;
+                }
+                "--output" => {
+                    config.output_json = match next_value(&mut args, &arg)?.as_str() {
+                        "text" => false,
+                        "json" => true,
+                        other => {
+                            return Err(invalid_input(format!("invalid output format: {}", other)))
+                        }
+                    };
+                }
+                "--relay" => config.relay = true,
+                "--max-in-flight" => {
+                    config.max_in_flight = Some(parse_positive(&next_value(&mut args, &arg)?)?);
+                }
+                "--restore-page" => {
+                    config.restore_page = Some(parse_duration(&next_value(&mut args, &arg)?)?);
+                }
+                "--preset-dir" => {
+                    config.preset_dir = PathBuf::from(next_value(&mut args, &arg)?);
+                }
+                "--time-format" => {
+                    config.time_format =
+                        TimeFormat::parse(&next_value(&mut args, &arg)?).map_err(invalid_input)?;
+                }
+                "--recp-check" => {
+                    config.recp_check = Some(parse_duration(&next_value(&mut args, &arg)?)?);
+                }
+                "--auto-prune" => {
+                    config.auto_prune = Some(parse_duration(&next_value(&mut args, &arg)?)?);
+                }
+                "--health-check" => {
+                    config.health_check = Some(parse_duration(&next_value(&mut args, &arg)?)?);
+                }
+                "--directory-window" => {
+                    config.directory_window = Some(parse_duration(&next_value(&mut args, &arg)?)?);
+                }
+                "--text-field" => config.text_field = Some(next_value(&mut args, &arg)?),
+                "--compact-window" => {
+                    config.compact_window = Some(parse_duration(&next_value(&mut args, &arg)?)?);
+                }
+                "--web-port" => {
+                    let value = next_value(&mut args, &arg)?;
+                    let port = value
+                        .parse()
+                        .map_err(|_| invalid_input(format!("invalid port: {}", value)))?;
+                    config.web_port = Some(port);
+                }
+                "--web-bind" => {
+                    let value = next_value(&mut args, &arg)?;
+                    config.web_bind = value
+                        .parse()
+                        .map_err(|_| invalid_input(format!("invalid address: {}", value)))?;
+                }
+                "--schema" => {
+                    let path = PathBuf::from(next_value(&mut args, &arg)?);
+                    config.schema = Some(Schema::load(&path)?);
+                }
+                "--schema-mode" => {
+                    config.schema_mode = match next_value(&mut args, &arg)?.as_str() {
+                        "flag" => SchemaMode::Flag,
+                        "reject" => SchemaMode::Reject,
+                        other => {
+                            return Err(invalid_input(format!("invalid schema mode: {}", other)))
+                        }
+                    };
+                }
+                "--denied-cooldown" => {
+                    config.denied_cooldown = Some(parse_duration(&next_value(&mut args, &arg)?)?);
+                }
+                "--reapprove-on-denied" => {
+                    config.reapprove_on_denied =
+                        Some(parse_duration(&next_value(&mut args, &arg)?)?);
+                }
+                "--event-log" => {
+                    config.event_log = Some(PathBuf::from(next_value(&mut args, &arg)?));
+                }
+                "--replay" => {
+                    config.replay = Some(PathBuf::from(next_value(&mut args, &arg)?));
+                }
+                "--replay-fast" => config.replay_fast = true,
+                "--recp-file" => {
+                    let value = next_value(&mut args, &arg)?;
+                    config
+                        .file_recps
+                        .extend(read_relations(&PathBuf::from(value))?);
+                }
+                "--once" => {
+                    config.mode = Mode::Send;
+                    config.once = Some(next_value(&mut args, &arg)?);
+                }
+                "--once-wait" => config.once_wait = true,
+                "--tail" => config.mode = Mode::Tail,
+                "--stress-duration" => {
+                    config.stress.duration = parse_duration(&next_value(&mut args, &arg)?)?;
+                }
+                "--stress-start-rate" => {
+                    config.stress.start_rate = parse_number(&next_value(&mut args, &arg)?)?;
+                }
+                "--stress-end-rate" => {
+                    config.stress.end_rate = parse_number(&next_value(&mut args, &arg)?)?;
+                }
+                "--stress-min-size" => {
+                    config.stress.min_size = parse_size(&next_value(&mut args, &arg)?)?;
+                }
+                "--stress-max-size" => {
+                    config.stress.max_size = parse_size(&next_value(&mut args, &arg)?)?;
+                }
+                "--stress-recps" => {
+                    config.stress.recps = Some(parse_number(&next_value(&mut args, &arg)?)?);
+                }
+                "--max-dataset-len" => {
+                    config.max_dataset_len = parse_number(&next_value(&mut args, &arg)?)?;
+                }
+                "--log-unknown-events" => config.log_unknown_events = true,
+                "--sanitize" => {
+                    config.sanitize = match next_value(&mut args, &arg)?.as_str() {
+                        "strip" => SanitizePolicy::Strip,
+                        "reject" => SanitizePolicy::Reject,
+                        other => {
+                            return Err(invalid_input(format!(
+                                "invalid sanitize policy: {}",
+                                other
+                            )))
+                        }
+                    };
+                }
+                "--router" => config.router_addr = next_value(&mut args, &arg)?,
+                "--bridge" => config.bridge_addr = Some(next_value(&mut args, &arg)?),
+                "--bridge-both" => config.bridge_both = true,
+                "--max-retries" => {
+                    config.max_retries = parse_number(&next_value(&mut args, &arg)?)?;
+                }
+                "--retry-backoff" => {
+                    config.retry_backoff = parse_duration(&next_value(&mut args, &arg)?)?;
+                }
+                "--name" => config.name = next_value(&mut args, &arg)?,
+                "--log-max-size" => {
+                    config.log_max_size = Some(parse_size(&next_value(&mut args, &arg)?)?);
+                }
+                "--log-keep" => {
+                    config.log_keep = parse_number(&next_value(&mut args, &arg)?)?;
+                }
+                "--dedup-window" => {
+                    config.dedup_window = parse_number(&next_value(&mut args, &arg)?)?;
+                }
+                "--msg-prefix" => config.msg_prefix = next_value(&mut args, &arg)?,
+                "--msg-suffix" => config.msg_suffix = next_value(&mut args, &arg)?,
+                "--encoding" => {
+                    let value = next_value(&mut args, &arg)?;
+                    config.encoding = Encoding::from_tag(&value)
+                        .ok_or_else(|| invalid_input(format!("invalid encoding: {}", value)))?;
+                }
+                "--download-dir" => {
+                    config.download_dir = PathBuf::from(next_value(&mut args, &arg)?);
+                }
+                "--max-msgs" => {
+                    config.max_msgs = parse_number(&next_value(&mut args, &arg)?)?;
+                }
+                "--page-delay" => {
+                    config.page_delay = Some(parse_duration(&next_value(&mut args, &arg)?)?);
+                }
+                "--subscribe-pattern" => {
+                    config.event_patterns.push(next_value(&mut args, &arg)?);
+                }
+                "--auto-add-senders" => config.auto_add_senders = true,
+                "--reconnect" => config.reconnect = true,
+                "--announce-reconnect" => config.announce_reconnect = true,
+                "--confirm-sends" => config.confirm_sends = true,
+                "--auto-clear-idle" => {
+                    config.auto_clear_idle = Some(parse_duration(&next_value(&mut args, &arg)?)?);
+                }
+                "--ping-timeout" => {
+                    config.ping_timeout = parse_duration(&next_value(&mut args, &arg)?)?;
+                }
+                "--send-rate" => {
+                    config.send_rate = Some(parse_positive(&next_value(&mut args, &arg)?)?);
+                }
+                "--persist-dir" => {
+                    config.persist_dir = Some(PathBuf::from(next_value(&mut args, &arg)?));
+                }
+                "--persist-format" => {
+                    let value = next_value(&mut args, &arg)?;
+                    config.persist_format = PersistFormat::from_tag(&value).ok_or_else(|| {
+                        invalid_input(format!("invalid persist format: {}", value))
+                    })?;
+                }
+                "--ring-size" => {
+                    config.ring_size = parse_number(&next_value(&mut args, &arg)?)?;
+                }
+                "--dump-file" => {
+                    config.dump_path = PathBuf::from(next_value(&mut args, &arg)?);
+                }
+                "--audit-log" => {
+                    config.audit_log = Some(PathBuf::from(next_value(&mut args, &arg)?));
+                }
+                "--in-order" => {
+                    config.in_order = Some(parse_duration(&next_value(&mut args, &arg)?)?);
+                }
+                "--page-file" => {
+                    config.page_file = Some(PathBuf::from(next_value(&mut args, &arg)?));
+                }
+                "--latency-count" => {
+                    config.latency_count = parse_positive(&next_value(&mut args, &arg)?)?;
+                }
+                "--auto-respond" => config.auto_respond = Some(next_value(&mut args, &arg)?),
+                "--inject-faults" => {
+                    let rates =
+                        FaultRates::parse(&next_value(&mut args, &arg)?).map_err(invalid_input)?;
+                    config.inject_faults = Some(rates);
+                }
+                "--fault-delay" => {
+                    config.fault_delay = parse_duration(&next_value(&mut args, &arg)?)?;
+                }
+                "--transform-out" => {
+                    config.transform_out =
+                        Pipeline::parse(&next_value(&mut args, &arg)?).map_err(invalid_input)?;
+                }
+                "--transform-in" => {
+                    config.transform_in =
+                        Pipeline::parse(&next_value(&mut args, &arg)?).map_err(invalid_input)?;
+                }
+                "--batch-window" => {
+                    config.batch_window = Some(parse_duration(&next_value(&mut args, &arg)?)?);
+                }
+                "--max-viewers" => {
+                    config.max_viewers = parse_number(&next_value(&mut args, &arg)?)?;
+                }
+                "--persist-passphrase" => {
+                    config.persist_passphrase = Some(next_value(&mut args, &arg)?);
+                }
+                "--sign" => config.sign = true,
+                "--sign-key-file" => {
+                    config.sign_key_path = PathBuf::from(next_value(&mut args, &arg)?);
+                }
+                "--prop" => {
+                    let (key, value) = parse_prop(&next_value(&mut args, &arg)?)?;
+                    config.identity_props.insert(key, value);
+                }
+                _ => {
+                    return Err(invalid_input(format!("unknown argument: {}", arg)));
+                }
+            }
+        }
+        for flag in &flags {
+            if !flag_modes(flag).contains(&config.mode) {
+                return Err(invalid_input(format!(
+                    "{} does not apply to {}",
+                    flag,
+                    config.mode.name()
+                )));
+            }
+        }
+        Ok(config)
+    }
+
+    pub fn log_rotation(&self) -> Option<Rotation> {
+        self.log_max_size.map(|max_bytes| Rotation {
+            max_bytes,
+            keep: self.log_keep,
+        })
+    }
+}
+
+/// The modes a flag is accepted in. Anything not listed belongs to the page.
+fn flag_modes(flag: &str) -> &'static [Mode] {
+    match flag {
+        "--router"
+        | "--approval-code-file"
+        | "--reset-on-denied"
+        | "--reset-keyfile"
+        | "--reapprove-on-denied" => &[
+            Mode::Run,
+            Mode::Send,
+            Mode::Monitor,
+            Mode::Stress,
+            Mode::Tail,
+        ],
+        "--allow" | "--allowlist" | "--subscribe-pattern" => {
+            &[Mode::Run, Mode::Monitor, Mode::Tail]
+        }
+        "--tail" => &[Mode::Tail],
+        "--time-format" => &[Mode::Run, Mode::Tail],
+        "--output" | "--color" => &[Mode::Run, Mode::Monitor, Mode::Tail],
+        "--cc" | "--ack-window" => &[Mode::Run, Mode::Send, Mode::Stress],
+        "--recp-file" => &[Mode::Send, Mode::Stress],
+        "--once" | "--once-wait" => &[Mode::Send],
+        flag if flag.starts_with("--stress-") => &[Mode::Stress],
+        _ => &[Mode::Run],
+    }
+}
+
+fn next_value(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<String, io::Error> {
+    args.next()
+        .ok_or_else(|| invalid_input(format!("{} requires a value", flag)))
+}
+
+/// Parse a duration such as `250ms`, `30s`, `5m` or `1h`. A bare number is seconds.
+pub fn parse_duration(text: &str) -> Result<Duration, io::Error> {
+    let text = text.trim();
+    let split = text
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let number: f64 = number
+        .parse()
+        .map_err(|_| invalid_input(format!("invalid duration: {}", text)))?;
+    let secs = match unit {
+        "ms" => number / 1000.0,
+        "" | "s" => number,
+        "m" => number * 60.0,
+        "h" => number * 3600.0,
+        _ => return Err(invalid_input(format!("invalid duration: {}", text))),
+    };
+    Duration::try_from_secs_f64(secs)
+        .map_err(|_| invalid_input(format!("duration out of range: {}", text)))
+}
+
+/// Parse a byte count such as `512`, `64K`, `10M` or `1G`.
+fn parse_size(text: &str) -> Result<u64, io::Error> {
+    let text = text.trim();
+    let (number, multiplier) = match text.char_indices().last() {
+        Some((idx, 'K' | 'k')) => (&text[..idx], 1 << 10),
+        Some((idx, 'M' | 'm')) => (&text[..idx], 1 << 20),
+        Some((idx, 'G' | 'g')) => (&text[..idx], 1 << 30),
+        _ => (text, 1),
+    };
+    let number: u64 = parse_number(number.trim())?;
+    number
+        .checked_mul(multiplier)
+        .ok_or_else(|| invalid_input(format!("size out of range: {}", text)))
+}
+
+/// Parse an identity property written as `key=value`.
+pub fn parse_prop(text: &str) -> Result<(String, String), io::Error> {
+    match text.split_once('=') {
+        Some((key, value)) if !key.trim().is_empty() => {
+            Ok((key.trim().to_string(), value.trim().to_string()))
+        }
+        _ => Err(invalid_input(format!("expected key=value, got {:?}", text))),
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(text: &str) -> Result<T, io::Error> {
+    text.parse()
+        .map_err(|_| invalid_input(format!("invalid number: {}", text)))
+}
+
+/// Parse a count that must be at least 1.
+fn parse_positive(text: &str) -> Result<usize, io::Error> {
+    match parse_number(text)? {
+        0 => Err(invalid_input(format!("not a positive number: {}", text))),
+        number => Ok(number),
+    }
+}
+
+fn parse_relation(text: &str) -> Result<Relation, io::Error> {
+    relation::parse(text).map_err(invalid_input)
+}
+
+/// Read one base-64 relation per line, skipping blank lines and `#` comments.
+fn read_relations(path: &Path) -> Result<Vec<Relation>, io::Error> {
+    let contents = fs::read_to_string(path)?;
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(parse_relation)
+        .collect()
+}
+
+fn invalid_input(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Config, String> {
+        Config::parse(args.iter().map(|arg| arg.to_string())).map_err(|e| e.to_string())
+    }
+
+    fn error(args: &[&str]) -> String {
+        match parse(args) {
+            Ok(_) => panic!("{:?} should be rejected", args),
+            Err(e) => e,
+        }
+    }
+
+    #[test]
+    fn subcommand_selects_the_mode() {
+        assert!(parse(&[]).is_ok_and(|config| config.mode == Mode::Run));
+        assert!(parse(&["run"]).is_ok_and(|config| config.mode == Mode::Run));
+        assert!(parse(&["monitor"]).is_ok_and(|config| config.mode == Mode::Monitor));
+        assert!(parse(&["stress"]).is_ok_and(|config| config.mode == Mode::Stress));
+        assert!(parse(&["tail"]).is_ok_and(|config| config.mode == Mode::Tail));
+        let config = parse(&["send", "hello"]).expect("send parses");
+        assert!(config.mode == Mode::Send);
+        assert_eq!(config.once.as_deref(), Some("hello"));
+        assert_eq!(error(&["send"]), "send requires a message");
+    }
+
+    #[test]
+    fn flag_outside_its_modes_is_rejected() {
+        assert_eq!(
+            error(&["monitor", "--title", "x"]),
+            "--title does not apply to monitor"
+        );
+        assert_eq!(error(&["--once-wait"]), "--once-wait does not apply to run");
+        assert!(parse(&["send", "hi", "--once-wait"]).is_ok_and(|config| config.once_wait));
+        assert!(parse(&["tail", "--time-format", "rfc3339"]).is_ok());
+        assert_eq!(
+            error(&["--no-such-flag"]),
+            "unknown argument: --no-such-flag"
+        );
+        assert_eq!(error(&["--title"]), "--title requires a value");
+    }
+
+    #[test]
+    fn counts_must_be_positive() {
+        assert_eq!(parse_positive("3").ok(), Some(3));
+        assert!(parse_positive("0").is_err());
+        assert!(parse_positive("-1").is_err());
+        assert!(error(&["--max-in-flight", "0"]).contains("not a positive number"));
+        assert!(error(&["--send-rate", "0"]).contains("not a positive number"));
+        assert!(error(&["--latency-count", "0"]).contains("not a positive number"));
+    }
+
+    #[test]
+    fn durations_out_of_range_are_rejected() {
+        assert_eq!(
+            parse_duration("250ms").ok(),
+            Some(Duration::from_millis(250))
+        );
+        assert_eq!(parse_duration("2m").ok(), Some(Duration::from_secs(120)));
+        let huge = "9".repeat(400);
+        assert!(parse_duration(&huge)
+            .is_err_and(|e| e.to_string().starts_with("duration out of range")));
+        assert!(parse_duration("99999999999999999999999h")
+            .is_err_and(|e| e.to_string().starts_with("duration out of range")));
+        assert!(parse_duration("-5s").is_err());
+        assert!(parse_duration("5d").is_err());
+    }
+
+    #[test]
+    fn sizes_out_of_range_are_rejected() {
+        assert_eq!(parse_size("64K").ok(), Some(64 << 10));
+        assert_eq!(parse_size("512").ok(), Some(512));
+        assert!(parse_size("20000000000G")
+            .is_err_and(|e| e.to_string().starts_with("size out of range")));
+        assert!(parse_size("99999999999999999999").is_err());
+        assert!(parse_size("10X").is_err());
+    }
+}
@@ -0,0 +1,40 @@
+use std::collections::{HashSet, VecDeque};
+
+/// Remembers the most recent message keys so repeated deliveries can be
+/// recognised. Once full, the oldest key is forgotten first.
+pub struct Dedup {
+    capacity: usize,
+    order: VecDeque<String>,
+    seen: HashSet<String>,
+}
+
+impl Dedup {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            order: VecDeque::new(),
+            seen: HashSet::new(),
+        }
+    }
+
+    /// Record `key`, returning false if it was already seen.
+    pub fn insert(&mut self, key: String) -> bool {
+        if self.capacity == 0 {
+            return true;
+        }
+        if self.seen.contains(&key) {
+            // refresh so a key that keeps arriving is not evicted
+            self.order.retain(|k| k != &key);
+            self.order.push_back(key);
+            return false;
+        }
+        if self.order.len() >= self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.seen.remove(&oldest);
+            }
+        }
+        self.seen.insert(key.clone());
+        self.order.push_back(key);
+        true
+    }
+}
@@ -0,0 +1,162 @@
+use std::collections::{BTreeMap, HashMap};
+
+use spider_client::{message::DirectoryEntry, Relation};
+
+use crate::relation;
+
+/// An identity published in the router's directory.
+pub struct Identity {
+    pub relation: Relation,
+    pub properties: BTreeMap<String, String>,
+}
+
+impl Identity {
+    pub fn name(&self) -> Option<&str> {
+        self.properties.get("name").map(String::as_str)
+    }
+}
+
+/// Local copy of the router directory, kept up to date while subscribed.
+#[derive(Default)]
+pub struct Directory {
+    identities: HashMap<Relation, Identity>,
+}
+
+impl Directory {
+    pub fn add(&mut self, entry: DirectoryEntry) {
+        let relation = entry.rel().clone();
+        let properties = entry
+            .properties()
+            .iter()
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+        self.identities.insert(
+            relation.clone(),
+            Identity {
+                relation,
+                properties,
+            },
+        );
+    }
+
+    pub fn remove(&mut self, relation: &Relation) {
+        self.identities.remove(relation);
+    }
+
+    /// Update one property of a known identity, returning whether it was
+    /// known.
+    pub fn set_property(&mut self, relation: &Relation, key: String, value: String) -> bool {
+        match self.identities.get_mut(relation) {
+            Some(identity) => {
+                identity.properties.insert(key, value);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.identities.clear();
+    }
+
+    pub fn contains(&self, relation: &Relation) -> bool {
+        self.identities.contains_key(relation)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.identities.is_empty()
+    }
+
+    /// Every identity's relation.
+    pub fn relations(&self) -> Vec<Relation> {
+        self.identities.keys().cloned().collect()
+    }
+
+    /// A published property of `relation`.
+    pub fn property(&self, relation: &Relation, key: &str) -> Option<&str> {
+        self.identities
+            .get(relation)?
+            .properties
+            .get(key)
+            .map(String::as_str)
+    }
+
+    /// The published name of `relation`, if it has one.
+    pub fn name_of(&self, relation: &Relation) -> Option<&str> {
+        self.identities.get(relation)?.name()
+    }
+
+    /// Identities whose name matches `pattern`: a glob with `*` and `?` if
+    /// it contains either, otherwise a case-insensitive substring.
+    pub fn find_by_name(&self, pattern: &str) -> Vec<&Identity> {
+        let pattern = pattern.to_lowercase();
+        let mut found: Vec<&Identity> = self
+            .identities
+            .values()
+            .filter(|identity| {
+                let name = identity.name().unwrap_or_default().to_lowercase();
+                if pattern.contains(['*', '?']) {
+                    glob_match(&pattern, &name)
+                } else {
+                    name.contains(&pattern)
+                }
+            })
+            .collect();
+        found.sort_by_key(|identity| identity.name().map(str::to_string));
+        found
+    }
+
+    /// Identities whose property `key` is exactly `value`.
+    pub fn find_by_property(&self, key: &str, value: &str) -> Vec<&Identity> {
+        self.identities
+            .values()
+            .filter(|identity| identity.properties.get(key).map(String::as_str) == Some(value))
+            .collect()
+    }
+
+    /// One line per identity, sorted by name.
+    pub fn render(&self) -> String {
+        let mut lines: Vec<String> = self
+            .identities
+            .values()
+            .map(|identity| {
+                format!(
+                    "{} ({})",
+                    identity.name().unwrap_or("unnamed"),
+                    relation::display(&identity.relation)
+                )
+            })
+            .collect();
+        lines.sort();
+        lines.join("\n")
+    }
+}
+
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    // positions to resume from after the most recent `*`
+    let (mut p, mut t) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((star_p, star_t)) => {
+                    p = star_p + 1;
+                    t = star_t + 1;
+                    star = Some((star_p, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
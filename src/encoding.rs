@@ -0,0 +1,147 @@
+/// How binary data is carried inside a `DatasetData::String`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Base64,
+    Hex,
+    /// Send the bytes as-is; only valid UTF-8 can be sent this way.
+    None,
+}
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+impl Encoding {
+    /// The tag stored alongside encoded data so receivers can decode it.
+    pub fn tag(self) -> &'static str {
+        match self {
+            Encoding::Base64 => "base64",
+            Encoding::Hex => "hex",
+            Encoding::None => "none",
+        }
+    }
+
+    pub fn from_tag(tag: &str) -> Option<Self> {
+        match tag {
+            "base64" => Some(Encoding::Base64),
+            "hex" => Some(Encoding::Hex),
+            "none" => Some(Encoding::None),
+            _ => None,
+        }
+    }
+
+    pub fn encode(self, bytes: &[u8]) -> Result<String, String> {
+        match self {
+            Encoding::Base64 => Ok(encode_base64(bytes)),
+            Encoding::Hex => Ok(bytes.iter().map(|b| format!("{:02x}", b)).collect()),
+            Encoding::None => String::from_utf8(bytes.to_vec())
+                .map_err(|_| String::from("data is not valid UTF-8, choose another encoding")),
+        }
+    }
+
+    pub fn decode(self, text: &str) -> Result<Vec<u8>, String> {
+        match self {
+            Encoding::Base64 => decode_base64(text),
+            Encoding::Hex => decode_hex(text),
+            Encoding::None => Ok(text.as_bytes().to_vec()),
+        }
+    }
+}
+
+fn encode_base64(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+fn decode_base64(text: &str) -> Result<Vec<u8>, String> {
+    let text = text.trim_end_matches('=');
+    let mut out = Vec::with_capacity(text.len() * 3 / 4);
+    let mut acc = 0u32;
+    let mut bits = 0;
+    for c in text.bytes() {
+        let value = BASE64
+            .iter()
+            .position(|&b| b == c)
+            .ok_or_else(|| format!("invalid base64 character {:?}", c as char))?;
+        acc = acc << 6 | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8);
+        }
+    }
+    Ok(out)
+}
+
+fn decode_hex(text: &str) -> Result<Vec<u8>, String> {
+    if text.len() % 2 != 0 {
+        return Err(String::from("hex data has an odd length"));
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| {
+            text.get(i..i + 2)
+                // from_str_radix alone would take a sign, as in "+f"
+                .filter(|pair| pair.bytes().all(|b| b.is_ascii_hexdigit()))
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                .ok_or_else(|| format!("invalid hex at offset {}", i))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(encoding: Encoding, bytes: &[u8]) {
+        let encoded = encoding.encode(bytes).expect("encodes");
+        assert_eq!(encoding.decode(&encoded).as_deref(), Ok(bytes));
+    }
+
+    #[test]
+    fn every_byte_value_survives() {
+        let bytes: Vec<u8> = (0..=255).collect();
+        round_trip(Encoding::Base64, &bytes);
+        round_trip(Encoding::Hex, &bytes);
+    }
+
+    /// Lengths 0 to 3 cover no chunk, then each base64 padding case.
+    #[test]
+    fn short_inputs_survive() {
+        for len in 0..=3 {
+            let bytes = &[0xff, 0x00, 0x80][..len];
+            round_trip(Encoding::Base64, bytes);
+            round_trip(Encoding::Hex, bytes);
+        }
+    }
+
+    #[test]
+    fn base64_pads_to_whole_quads() {
+        let encode = |bytes: &[u8]| Encoding::Base64.encode(bytes).expect("encodes");
+        assert_eq!(encode(b""), "");
+        assert_eq!(encode(b"f"), "Zg==");
+        assert_eq!(encode(b"fo"), "Zm8=");
+        assert_eq!(encode(b"foo"), "Zm9v");
+    }
+
+    #[test]
+    fn bad_hex_is_rejected() {
+        assert!(Encoding::Hex.decode("abc").is_err());
+        assert!(Encoding::Hex.decode("zz").is_err());
+        assert!(Encoding::Hex.decode("+f").is_err());
+        assert!(Encoding::Hex.decode("\u{e9}").is_err());
+    }
+}
@@ -0,0 +1,113 @@
+use std::{
+    fs, io,
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde_json::{json, Value};
+use spider_client::{message::DatasetData, Relation};
+use tokio::sync::mpsc;
+
+use crate::{
+    relation,
+    rotate::{RotatingFile, Rotation},
+};
+
+/// An outbound event as recorded in the event log.
+pub struct LoggedEvent {
+    /// Milliseconds since the epoch when the event was sent.
+    pub at_ms: u64,
+    pub name: String,
+    pub recps: Vec<Relation>,
+    pub data: DatasetData,
+}
+
+/// Appends every outbound event to a JSONL file, one object per line:
+/// `{"at_ms": .., "name": .., "recps": [..], "data": ..}`.
+pub struct EventLog {
+    file: RotatingFile,
+}
+
+impl EventLog {
+    pub fn open(path: &Path, rotation: Option<Rotation>) -> Result<Self, io::Error> {
+        let file = RotatingFile::open(path, rotation)?;
+        Ok(Self { file })
+    }
+
+    pub fn record(&mut self, name: &str, recps: &[Relation], data: &DatasetData) {
+        let line = json!({
+            "at_ms": now_ms(),
+            "name": name,
+            "recps": recps.iter().map(relation::display).collect::<Vec<_>>(),
+            "data": data,
+        });
+        if let Err(e) = self.file.write_line(&line.to_string()) {
+            log!(Error; "Failed to write event log: {}", e);
+        }
+    }
+}
+
+/// Read a recorded event log. Lines that cannot be parsed are skipped.
+pub fn read(path: &Path) -> Result<Vec<LoggedEvent>, io::Error> {
+    let contents = fs::read_to_string(path)?;
+    let mut events = vec![];
+    for (line_no, line) in contents.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match parse_line(line) {
+            Some(event) => events.push(event),
+            None => log!("Skipping malformed event log line {}", line_no + 1),
+        }
+    }
+    Ok(events)
+}
+
+fn parse_line(line: &str) -> Option<LoggedEvent> {
+    let value: Value = serde_json::from_str(line).ok()?;
+    let at_ms = value.get("at_ms")?.as_u64()?;
+    let name = value.get("name")?.as_str()?.to_string();
+    let recps = value
+        .get("recps")?
+        .as_array()?
+        .iter()
+        .filter_map(Value::as_str)
+        .filter_map(|recp| relation::parse(recp).ok())
+        .collect();
+    let data = serde_json::from_value(value.get("data")?.clone()).ok()?;
+    Some(LoggedEvent {
+        at_ms,
+        name,
+        recps,
+        data,
+    })
+}
+
+/// Feed recorded events to the main loop, spaced out by their original
+/// relative timing unless `fast` is set.
+pub fn spawn_replay(events: Vec<LoggedEvent>, fast: bool) -> mpsc::Receiver<LoggedEvent> {
+    let (tx, rx) = mpsc::channel(16);
+    tokio::spawn(async move {
+        let Some(first) = events.first().map(|event| event.at_ms) else {
+            return;
+        };
+        let start = tokio::time::Instant::now();
+        for event in events {
+            if !fast {
+                let offset = Duration::from_millis(event.at_ms.saturating_sub(first));
+                tokio::time::sleep_until(start + offset).await;
+            }
+            if tx.send(event).await.is_err() {
+                break;
+            }
+        }
+    });
+    rx
+}
+
+pub fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
@@ -0,0 +1,117 @@
+use std::time::Duration;
+
+use spider_client::message::DatasetData;
+
+/// What happens to one outgoing send under `--inject-faults`.
+pub enum Fault {
+    None,
+    Drop,
+    Corrupt,
+    Delay(Duration),
+}
+
+/// Probabilities of each fault, from `drop=0.1,corrupt=0.05,delay=0.2`.
+#[derive(Clone, Default)]
+pub struct FaultRates {
+    pub drop: f64,
+    pub corrupt: f64,
+    pub delay: f64,
+}
+
+impl FaultRates {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut rates = Self::default();
+        for part in text.split(',').filter(|part| !part.trim().is_empty()) {
+            let (kind, rate) = part
+                .split_once('=')
+                .ok_or_else(|| format!("expected kind=probability, got {:?}", part))?;
+            let rate: f64 = rate
+                .trim()
+                .parse()
+                .ok()
+                .filter(|rate| (0.0..=1.0).contains(rate))
+                .ok_or_else(|| format!("invalid probability: {}", rate))?;
+            match kind.trim() {
+                "drop" => rates.drop = rate,
+                "corrupt" => rates.corrupt = rate,
+                "delay" => rates.delay = rate,
+                other => return Err(format!("unknown fault: {}", other)),
+            }
+        }
+        if rates.drop + rates.corrupt + rates.delay > 1.0 {
+            return Err(String::from("fault probabilities add up to more than 1"));
+        }
+        Ok(rates)
+    }
+}
+
+/// Picks a fault for each send at the configured rates.
+pub struct FaultInjector {
+    rates: FaultRates,
+    delay: Duration,
+    state: u64,
+}
+
+impl FaultInjector {
+    pub fn new(rates: FaultRates, delay: Duration) -> Self {
+        let mut seed = [0; 8];
+        // a fixed seed still gives the configured rates, just repeatably
+        let _ = getrandom::getrandom(&mut seed);
+        Self {
+            rates,
+            delay,
+            state: u64::from_le_bytes(seed) | 1,
+        }
+    }
+
+    pub fn roll(&mut self) -> Fault {
+        let roll = self.next_f64();
+        let rates = &self.rates;
+        if roll < rates.drop {
+            Fault::Drop
+        } else if roll < rates.drop + rates.corrupt {
+            Fault::Corrupt
+        } else if roll < rates.drop + rates.corrupt + rates.delay {
+            Fault::Delay(self.delay)
+        } else {
+            Fault::None
+        }
+    }
+
+    /// Damage a payload's text so receivers see it arrive malformed: half
+    /// of it is cut off and one character is replaced.
+    pub fn corrupt(&mut self, data: DatasetData) -> DatasetData {
+        match data {
+            DatasetData::String(text) => DatasetData::String(self.corrupt_text(&text)),
+            DatasetData::Map(mut map) => {
+                if let Some(DatasetData::String(text)) = map.get_mut("text") {
+                    *text = self.corrupt_text(text);
+                }
+                DatasetData::Map(map)
+            }
+            data => data,
+        }
+    }
+
+    fn corrupt_text(&mut self, text: &str) -> String {
+        let mut chars: Vec<char> = text.chars().collect();
+        chars.truncate(chars.len().div_ceil(2));
+        if !chars.is_empty() {
+            let idx = (self.next_u64() % chars.len() as u64) as usize;
+            chars[idx] = '\u{fffd}';
+        }
+        chars.into_iter().collect()
+    }
+
+    /// xorshift64, which is plenty for picking faults.
+    fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
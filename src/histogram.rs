@@ -0,0 +1,70 @@
+use spider_client::message::DatasetData;
+
+use crate::messages;
+
+const BAR_WIDTH: usize = 20;
+
+const SIZE_BUCKETS: &[(u64, &str)] = &[
+    (16, "<16B"),
+    (64, "<64B"),
+    (256, "<256B"),
+    (1024, "<1KiB"),
+    (u64::MAX, ">=1KiB"),
+];
+
+const GAP_BUCKETS: &[(u64, &str)] = &[
+    (1, "<1s"),
+    (5, "<5s"),
+    (30, "<30s"),
+    (300, "<5m"),
+    (u64::MAX, ">=5m"),
+];
+
+/// Text bar charts of message sizes and the gaps between arrivals, from
+/// the `text` and `received_at` fields of Messages entries.
+pub fn render(msgs: &[DatasetData]) -> String {
+    let sizes: Vec<u64> = msgs
+        .iter()
+        .map(|entry| messages::text(entry).len() as u64)
+        .collect();
+    let times: Vec<u64> = msgs
+        .iter()
+        .filter_map(|entry| messages::field(entry, "received_at")?.parse().ok())
+        .collect();
+    let gaps: Vec<u64> = times
+        .windows(2)
+        .map(|pair| pair[1].saturating_sub(pair[0]))
+        .collect();
+    format!(
+        "Message sizes ({} messages)\n{}\nInter-arrival times ({} gaps)\n{}",
+        sizes.len(),
+        chart(&sizes, SIZE_BUCKETS),
+        gaps.len(),
+        chart(&gaps, GAP_BUCKETS)
+    )
+}
+
+fn chart(values: &[u64], buckets: &[(u64, &str)]) -> String {
+    let mut counts = vec![0; buckets.len()];
+    for &value in values {
+        if let Some(idx) = buckets.iter().position(|&(limit, _)| value < limit) {
+            counts[idx] += 1;
+        }
+    }
+    let max = counts.iter().copied().max().unwrap_or(0).max(1);
+    buckets
+        .iter()
+        .zip(&counts)
+        .map(|((_, label), &count)| {
+            let bar = "#".repeat(count * BAR_WIDTH / max);
+            format!(
+                "{:>7} | {:<width$} {}",
+                label,
+                bar,
+                count,
+                width = BAR_WIDTH
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
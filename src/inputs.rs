@@ -0,0 +1,73 @@
+/// Declare the page elements the router takes input from, once, as the
+/// [`Input`] enum together with the element id each variant stands for.
+macro_rules! inputs {
+    ($($variant:ident => $id:literal,)*) => {
+        /// An element whose input `input_handler` acts on.
+        #[derive(Clone, Copy, PartialEq, Eq, Debug)]
+        pub enum Input {
+            $($variant,)*
+        }
+
+        impl Input {
+            pub const ALL: &'static [Input] = &[$(Input::$variant,)*];
+
+            /// The id of the page element this input comes from.
+            pub fn id(self) -> &'static str {
+                match self {
+                    $(Input::$variant => $id,)*
+                }
+            }
+
+            pub fn from_id(id: &str) -> Option<Input> {
+                match id {
+                    $($id => Some(Input::$variant),)*
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+inputs! {
+    AddRecp => "Add Recp",
+    SavePreset => "Save Preset",
+    LoadPreset => "Load Preset",
+    Tag => "Tag",
+    MoveRecp => "Move Recp",
+    Mute => "Mute",
+    PruneRecps => "Prune Recps",
+    ClearRecps => "Clear Recps",
+    ImportRecps => "Import Recps",
+    SetProp => "Set Prop",
+    SendToRole => "Send To Role",
+    SendMsg => "Send Msg",
+    ConfirmSend => "Confirm Send",
+    SendFile => "Send File",
+    Whoami => "Whoami",
+    QueryCaps => "Query Caps",
+    TestAll => "Test All",
+    Schedule => "Schedule",
+    CancelScheduled => "Cancel Scheduled",
+    SubscribePattern => "Subscribe Pattern",
+    SwitchDataset => "Switch Dataset",
+    Latency => "Latency",
+    Stats => "Stats",
+    DumpEvents => "Dump Events",
+    ShowRaw => "Show Raw",
+    Pause => "Pause",
+    Filter => "Filter",
+    ResendFailed => "Resend Failed",
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ids_round_trip() {
+        for &input in Input::ALL {
+            assert_eq!(Input::from_id(input.id()), Some(input));
+        }
+        assert_eq!(Input::from_id("Nope"), None);
+    }
+}
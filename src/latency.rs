@@ -0,0 +1,89 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use spider_client::Relation;
+
+use crate::relation;
+
+/// A round of pings to one recipient, each with its own id, timed to
+/// build a latency distribution.
+///
+/// Each ping is timed from when it actually leaves, so time spent held
+/// under `--max-in-flight` or by an injected delay is not counted. The
+/// deadline runs from the latest ping sent.
+pub struct LatencyRun {
+    recp: Relation,
+    /// Unanswered pings, with when they were sent once they have been.
+    sent: HashMap<String, Option<Instant>>,
+    timeout: Duration,
+    rtts: Vec<Duration>,
+    deadline: Instant,
+}
+
+impl LatencyRun {
+    pub fn new(recp: Relation, ids: &[String], now: Instant, timeout: Duration) -> Self {
+        Self {
+            recp,
+            sent: ids.iter().map(|id| (id.clone(), None)).collect(),
+            timeout,
+            rtts: vec![],
+            deadline: now + timeout,
+        }
+    }
+
+    /// Note that the ping with `id` has just been handed to the router.
+    pub fn mark_sent(&mut self, id: &str, now: Instant) {
+        if let Some(sent_at) = self.sent.get_mut(id) {
+            *sent_at = Some(now);
+            self.deadline = self.deadline.max(now + self.timeout);
+        }
+    }
+
+    /// Record a pong. Returns whether it answered one of this run's pings.
+    pub fn record(&mut self, id: &str, from: &Relation, now: Instant) -> bool {
+        if *from != self.recp {
+            return false;
+        }
+        match self.sent.get(id) {
+            Some(&Some(sent_at)) => {
+                self.sent.remove(id);
+                self.rtts.push(now.duration_since(sent_at));
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether every ping has been answered or the deadline has passed.
+    pub fn is_done(&self, now: Instant) -> bool {
+        now >= self.deadline || self.sent.is_empty()
+    }
+
+    pub fn render(&self) -> String {
+        let total = self.rtts.len() + self.sent.len();
+        let mut header = format!(
+            "Latency to {}: {}/{} answered",
+            relation::display(&self.recp),
+            self.rtts.len(),
+            total
+        );
+        let mut rtts = self.rtts.clone();
+        if rtts.is_empty() {
+            return header;
+        }
+        rtts.sort();
+        let percentile = |p: usize| rtts[(rtts.len() * p / 100).min(rtts.len() - 1)];
+        let mean = rtts.iter().sum::<Duration>() / rtts.len() as u32;
+        header.push_str(&format!(
+            "\nmin {}ms, max {}ms, mean {}ms, p50 {}ms, p95 {}ms",
+            rtts[0].as_millis(),
+            rtts[rtts.len() - 1].as_millis(),
+            mean.as_millis(),
+            percentile(50).as_millis(),
+            percentile(95).as_millis()
+        ));
+        header
+    }
+}
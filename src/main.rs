@@ -1,4 +1,83 @@
-use std::{io, path::PathBuf};
+use std::{
+    collections::{HashMap, VecDeque},
+    io,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+#[macro_use]
+mod output;
+mod audit;
+mod clock;
+mod compact;
+mod config;
+mod dedup;
+mod directory;
+mod encoding;
+mod event_log;
+mod faults;
+mod histogram;
+mod inputs;
+mod latency;
+mod mention;
+mod messages;
+mod monitor;
+mod once;
+mod ordering;
+mod persist;
+mod ping;
+mod presets;
+mod queue;
+mod reconcile;
+mod relation;
+mod repl;
+mod ring;
+mod rotate;
+mod sanitize;
+mod scheduler;
+mod schema;
+mod search;
+mod sends;
+mod sign;
+mod slash;
+mod stats;
+mod stress;
+mod subscriptions;
+mod template;
+mod timefmt;
+mod traffic;
+mod transform;
+mod trim;
+mod viewer;
+mod web;
+use audit::AuditLog;
+use clock::{Clock, SystemClock};
+use config::{Config, Mode, NoRecpBehavior, SendMode};
+use dedup::Dedup;
+use directory::Directory;
+use event_log::EventLog;
+use faults::{Fault, FaultInjector};
+use inputs::Input;
+use latency::LatencyRun;
+use messages::MessageIds;
+use ordering::Sequencer;
+use persist::Cache;
+use ping::PingBatch;
+use queue::{Priority, SendOptions, SendQueue};
+use reconcile::{Reconciled, Reconciler};
+use repl::Command;
+use ring::MessageRing;
+use scheduler::Scheduler;
+use schema::SchemaMode;
+use search::SearchIndex;
+use sends::{SendStatus, SendTracker};
+use sign::Signer;
+use slash::SlashCommand;
+use stats::RecpStatsTable;
+use subscriptions::Subscriptions;
+use traffic::Traffic;
+use trim::Trimmer;
+use viewer::ViewerState;
 
 use spider_client::{
     message::{
@@ -9,71 +88,434 @@ use spider_client::{
     ClientChannel, ClientResponse, Relation, SpiderClientBuilder,
 };
 
+/// How long the Feedback element shows that an input was handled.
+const FEEDBACK_TIME: Duration = Duration::from_secs(3);
+
+const KEYFILE: &str = "spider_keyfile.json";
+/// File in `--persist-dir` holding the subscription set.
+const SUBSCRIPTIONS_FILE: &str = "subscriptions.json";
+const BRIDGE_STATE: &str = "client_state_bridge.dat";
+
+/// Events this router subscribes to and handles.
+const EVENTS: &[&str] = &[
+    "test_event",
+    "receipt",
+    "whoami",
+    "whoami_reply",
+    "file",
+    "reconnected",
+    "ping",
+    "pong",
+    "capabilities",
+    "capabilities_reply",
+];
+
 #[tokio::main]
 async fn main() -> Result<(), io::Error> {
+    let config = Config::from_args()?;
+    output::set_json(config.output_json);
+    output::set_color(config.color);
     let client_path = PathBuf::from("client_state.dat");
 
-    let mut builder = SpiderClientBuilder::load_or_set(&client_path, |builder| {
-        builder.enable_fixed_addrs(true);
-        builder.set_fixed_addrs(vec!["localhost:1930".into()]);
-    });
+    let replay = match &config.replay {
+        Some(path) => event_log::read(path)?,
+        None => vec![],
+    };
+    let mut replay = event_log::spawn_replay(replay, config.replay_fast);
+
+    let mut client_channel = connect(&client_path, config.router_addr.clone()).await;
+    // the run mode counts its traffic in State instead
+    let mut traffic = Traffic::default();
+    match config.mode {
+        Mode::Run => {}
+        Mode::Send => {
+            let text = config.once.clone().unwrap_or_default();
+            let delivered = once::run(&mut client_channel, &config, text, &mut traffic).await;
+            log!("Traffic: {}", traffic.render());
+            std::process::exit(if delivered { 0 } else { 1 });
+        }
+        Mode::Monitor | Mode::Tail => {
+            monitor::run(&mut client_channel, &config, &mut traffic).await;
+            log!("Traffic: {}", traffic.render());
+            return Ok(());
+        }
+        Mode::Stress => {
+            let clean = stress::run(&mut client_channel, &config, &mut traffic).await;
+            log!("Traffic: {}", traffic.render());
+            std::process::exit(if clean { 0 } else { 1 });
+        }
+    }
 
-    builder.try_use_keyfile("spider_keyfile.json").await;
+    // A second connection whose events are bridged with the primary one
+    let mut bridge = match &config.bridge_addr {
+        Some(addr) => {
+            let mut channel = connect(Path::new(BRIDGE_STATE), addr.clone()).await;
+            let mut bridge_config = config.clone();
+            bridge_config.page_title = format!("{} (bridge)", config.page_title);
+            bridge_config.web_port = None;
+            bridge_config.audit_log = None;
+            let mut bridge_state =
+                State::init(&mut channel, bridge_config, Box::new(SystemClock)).await;
+            bridge_state.forwarding = config.bridge_both;
+            Some((channel, bridge_state))
+        }
+        None => None,
+    };
+
+    let forwarding = bridge.is_some();
+    let mut state = State::init(&mut client_channel, config, Box::new(SystemClock)).await;
+    state.forwarding = forwarding;
 
-    let mut client_channel = builder.start(true);
-    let mut state = State::init(&mut client_channel).await;
+    let mut stdin = repl::spawn_stdin_reader();
+    let mut tick = tokio::time::interval(Duration::from_secs(1));
 
     loop {
-        match client_channel.recv().await {
-            Some(ClientResponse::Message(msg)) => {
-                state.msg_handler(&mut client_channel, msg).await;
+        tokio::select! {
+            response = client_channel.recv() => match response {
+                Some(ClientResponse::Message(msg)) => {
+                    state.msg_handler(&mut client_channel, msg).await;
+                    if let Some((channel, bridge_state)) = &mut bridge {
+                        for (name, data) in state.take_forwarded() {
+                            bridge_state.forward(channel, name, data).await;
+                        }
+                    }
+                    if std::mem::take(&mut state.denied) {
+                        reset_identity(&client_path, &state.config);
+                        if let Some(delay) = state.config.reapprove_on_denied {
+                            client_channel = reapprove(&client_path, &mut state, delay).await;
+                        }
+                    }
+                }
+                Some(ClientResponse::Denied(_)) => {
+                    state.denied_at = Some(state.clock.now());
+                    reset_identity(&client_path, &state.config);
+                    match state.config.reapprove_on_denied {
+                        Some(delay) => {
+                            client_channel = reapprove(&client_path, &mut state, delay).await;
+                        }
+                        None => break,
+                    }
+                }
+                None if state.config.reconnect => {
+                    log!("Router connection closed, reconnecting");
+                    wait_after_denial(&state).await;
+                    client_channel = connect(&client_path, state.config.router_addr.clone()).await;
+                    state.reconnect(&mut client_channel).await;
+                }
+                None => break, //  done!
+                _ => {}
+            },
+            response = recv_bridge(&mut bridge) => match response {
+                Some(ClientResponse::Message(msg)) => {
+                    let (channel, bridge_state) =
+                        bridge.as_mut().expect("only polled with a bridge");
+                    bridge_state.msg_handler(channel, msg).await;
+                    for (name, data) in bridge_state.take_forwarded() {
+                        state.forward(&mut client_channel, name, data).await;
+                    }
+                }
+                Some(ClientResponse::Denied(_)) | None => {
+                    log!("Bridge connection closed");
+                    bridge = None;
+                }
+                _ => {}
+            },
+            Some(line) = stdin.recv() => match Command::parse(&line) {
+                Ok(Command::Quit) => break,
+                Ok(cmd) => state.command_handler(&mut client_channel, cmd).await,
+                Err(e) => log!("{}", e),
+            },
+            Some(event) = replay.recv() => {
+                state.send_event(&mut client_channel, event.name, event.recps, event.data).await;
+            }
+            _ = tick.tick() => {
+                state.tick(&mut client_channel).await;
+                if std::mem::take(&mut state.unhealthy) {
+                    log!("Router connection unresponsive, reconnecting");
+                    wait_after_denial(&state).await;
+                    client_channel = connect(&client_path, state.config.router_addr.clone()).await;
+                    state.reconnect(&mut client_channel).await;
+                }
+                if let Some((channel, bridge_state)) = &mut bridge {
+                    bridge_state.tick(channel).await;
+                }
             }
-            Some(ClientResponse::Denied(_)) => break,
-            None => break, //  done!
-            _ => {}
         }
     }
 
+    if let Some(cache) = &mut state.cache {
+        cache.finish().await;
+    }
+    if let Some((_, bridge_state)) = &mut bridge {
+        if let Some(cache) = &mut bridge_state.cache {
+            cache.finish().await;
+        }
+    }
     Ok(())
 }
 
+/// Read the page elements for `--page-file`, written as a JSON array that
+/// becomes the children of the page root.
+fn load_page(path: &Path) -> Result<Vec<UiElement>, io::Error> {
+    let contents = std::fs::read_to_string(path)?;
+    serde_json::from_str(&contents).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Start a client from the state file, creating one that connects to
+/// `addr` if it does not exist yet.
+async fn connect(state_path: &Path, addr: String) -> ClientChannel {
+    let mut builder = SpiderClientBuilder::load_or_set(state_path, |builder| {
+        builder.enable_fixed_addrs(true);
+        builder.set_fixed_addrs(vec![addr]);
+    });
+
+    builder.try_use_keyfile(KEYFILE).await;
+
+    builder.start(true)
+}
+
+/// Connect again after a denial so the router starts a fresh approval.
+async fn reapprove(client_path: &Path, state: &mut State, delay: Duration) -> ClientChannel {
+    log!(Auth; "Denied, requesting approval again in {:?}", delay);
+    tokio::time::sleep(delay).await;
+    wait_after_denial(state).await;
+    let mut channel = connect(client_path, state.config.router_addr.clone()).await;
+    state.reconnect(&mut channel).await;
+    channel
+}
+
+/// Hold off until `--denied-cooldown` has passed since the last denial, so
+/// a router that keeps denying this client is not asked again right away.
+async fn wait_after_denial(state: &State) {
+    let (Some(cooldown), Some(at)) = (state.config.denied_cooldown, state.denied_at) else {
+        return;
+    };
+    let remaining = cooldown.saturating_sub(state.clock.now().duration_since(at));
+    if !remaining.is_zero() {
+        log!(Auth; "Recently denied, waiting {:?} before connecting", remaining);
+        tokio::time::sleep(remaining).await;
+    }
+}
+
+async fn recv_bridge(bridge: &mut Option<(ClientChannel, State)>) -> Option<ClientResponse> {
+    match bridge {
+        Some((channel, _)) => channel.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Delete the persisted identity after a denial if configured to.
+fn reset_identity(client_path: &Path, config: &Config) {
+    if config.reset_on_denied {
+        remove_identity(client_path);
+        if config.reset_keyfile {
+            remove_identity(Path::new(KEYFILE));
+        }
+    }
+}
+
+/// Delete a persisted identity file so the next launch starts fresh.
+fn remove_identity(path: &Path) {
+    match std::fs::remove_file(path) {
+        Ok(()) => log!(Auth; "Removed {} after denial", path.display()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => log!(Error; "Failed to remove {}: {}", path.display(), e),
+    }
+}
+
+fn recp_dataset() -> DatasetPath {
+    DatasetPath::new_private(vec![String::from("Recp")])
+}
+
+fn msgs_dataset() -> DatasetPath {
+    DatasetPath::new_private(vec![String::from("Messages")])
+}
+
+/// Dataset collecting events of one name matched by a pattern subscription.
+fn event_dataset(name: &str) -> DatasetPath {
+    DatasetPath::new_private(vec![String::from("Events"), name.to_string()])
+}
+
+fn unhandled_dataset() -> DatasetPath {
+    DatasetPath::new_private(vec![String::from("Unhandled")])
+}
+
 struct State {
+    config: Config,
+    page: UiPageManager,
     recps: Vec<DatasetData>,
     msgs: Vec<DatasetData>,
+    /// Full sizes of the datasets, which `recps` and `msgs` may be capped below.
+    recps_len: usize,
+    msgs_len: usize,
+    sends: SendTracker,
+    /// Index of the next recipient in round-robin mode.
+    next_recp: usize,
+    /// When the last dataset or event message arrived, for the watchdog.
+    last_activity: Instant,
+    /// When any message last arrived, shown in the Last Message element.
+    last_msg_at: Option<Instant>,
+    /// When an event last arrived, for `--auto-clear-idle`. Reset once the
+    /// messages have been cleared so they are only cleared once per lull.
+    last_event_at: Option<Instant>,
+    search: SearchIndex,
+    /// Query entered in the Filter element, if any.
+    filter: Option<String>,
+    /// Viewers that sent input addressed to them, up to `--max-viewers`.
+    viewers: HashMap<Relation, ViewerState>,
+    event_log: Option<EventLog>,
+    /// Record of every UI input under `--audit-log`.
+    audit: Option<AuditLog>,
+    recp_stats: RecpStatsTable,
+    /// While paused, incoming messages are held in `paused_msgs`.
+    paused: bool,
+    paused_msgs: Vec<DatasetData>,
+    /// Messages held under `--batch-window`, and when the first arrived.
+    batch: Vec<DatasetData>,
+    batch_started: Option<Instant>,
+    /// How many times each unrecognized event name has arrived.
+    unknown_events: HashMap<String, u64>,
+    scheduler: Scheduler,
+    /// Whether received events are collected in `forwarded` for a bridge.
+    forwarding: bool,
+    forwarded: Vec<(String, DatasetData)>,
+    directory: Directory,
+    msg_ids: MessageIds,
+    /// Recently seen message ids, to drop repeated deliveries.
+    dedup: Dedup,
+    show_raw: bool,
+    trimmer: Trimmer,
+    subscriptions: Subscriptions,
+    /// Senders added by `--auto-add-senders` this session.
+    auto_added: Vec<Relation>,
+    reconciler: Reconciler,
+    /// Raw copies of recently received messages.
+    ring: MessageRing,
+    cache: Option<Cache>,
+    /// The HTML mirror served under `--web-port`.
+    web: Option<web::Mirror>,
+    /// Signs outgoing messages under `--sign`.
+    signer: Option<Signer>,
+    /// A typed message waiting for Confirm Send, with its recipients.
+    pending_send: Option<(String, Vec<Relation>, SendOptions)>,
+    /// Typed messages held back by `--send-rate`.
+    queue: SendQueue,
+    /// The Test All run in progress, if any.
+    ping: Option<PingBatch>,
+    /// The latency measurement in progress, if any.
+    latency: Option<LatencyRun>,
+    /// Datasets the Recp and Messages rows are bound to, which Switch
+    /// Dataset can change.
+    recp_path: DatasetPath,
+    msgs_path: DatasetPath,
+    /// Set when the router sends `Denied`, for the main loop to act on.
+    denied: bool,
+    /// When the router last denied this client, for `--denied-cooldown`.
+    denied_at: Option<Instant>,
+    /// Directory changes not yet shown, under `--directory-window`.
+    directory_dirty: bool,
+    directory_shown_at: Instant,
+    /// When the outstanding health probe was sent, and when the last one was.
+    health_probe: Option<Instant>,
+    last_probe: Instant,
+    /// Set when a probe went unanswered, for the main loop to reconnect.
+    unhealthy: bool,
+    last_prune: Instant,
+    /// When `--recp-check` last pinged the recipients.
+    last_recp_check: Instant,
+    /// When the Feedback element should be cleared.
+    feedback_until: Option<Instant>,
+    clock: Box<dyn Clock>,
+    /// When a viewer cleared the page, while it has not been sent again.
+    page_cleared_at: Option<Instant>,
+    /// Sends waiting for room under `--max-in-flight`.
+    held: VecDeque<(String, Vec<Relation>, DatasetData, u32)>,
+    /// Fault injection under `--inject-faults`, and the sends it delayed.
+    faults: Option<FaultInjector>,
+    delayed: VecDeque<(Instant, Message)>,
+    /// Number given to the next test event sent to each recipient.
+    seqs: HashMap<Relation, u64>,
+    /// Received test events held for ordering under `--in-order`.
+    sequencer: Option<Sequencer>,
+    /// Bytes sent and received so far.
+    traffic: Traffic,
 }
 
 impl State {
-    async fn init(client: &mut ClientChannel) -> Self {
-        let msg = RouterMessage::SetIdentityProperty("name".into(), "Test Router".into());
+    /// Set up the identity, subscriptions and page, reading time from `clock`.
+    async fn init(client: &mut ClientChannel, config: Config, clock: Box<dyn Clock>) -> Self {
+        let mut traffic = Traffic::default();
+        let msg = RouterMessage::SetIdentityProperty("name".into(), config.name.clone());
         let msg = Message::Router(msg);
+        traffic.record_sent(&msg);
         client.send(msg).await;
+        for (key, value) in &config.identity_props {
+            let msg = RouterMessage::SetIdentityProperty(key.clone(), value.clone());
+            let msg = Message::Router(msg);
+            traffic.record_sent(&msg);
+            client.send(msg).await;
+        }
+        let signer = if config.sign {
+            match Signer::load_or_create(&config.sign_key_path) {
+                Ok(signer) => {
+                    let msg = RouterMessage::SetIdentityProperty(
+                        String::from(sign::KEY_PROPERTY),
+                        signer.public_key(),
+                    );
+                    let msg = Message::Router(msg);
+                    traffic.record_sent(&msg);
+                    client.send(msg).await;
+                    Some(signer)
+                }
+                Err(e) => {
+                    log!(Error; "Failed to load signing key {}: {}",
+                        config.sign_key_path.display(), e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
 
-        // Subscribe to recp dataset
-        let recp_dataset = DatasetPath::new_private(vec![String::from("Recp")]);
-        let msg = Message::Dataset(DatasetMessage::Subscribe {
-            path: recp_dataset.clone(),
-        });
-        client.send(msg).await;
-
-        // Subscribe to Msgs dataset
-        let msgs_dataset = DatasetPath::new_private(vec![String::from("Messages")]);
-        let msg = Message::Dataset(DatasetMessage::Subscribe {
-            path: msgs_dataset.clone(),
-        });
-        client.send(msg).await;
-
-        // Subscribe to test_event
-        let msg = Message::Router(RouterMessage::Subscribe(String::from("test_event")));
-        client.send(msg).await;
+        let saved = config
+            .persist_dir
+            .as_ref()
+            .and_then(|dir| Subscriptions::load(&dir.join(SUBSCRIPTIONS_FILE)));
+        let subscriptions = match saved {
+            Some(mut saved) => {
+                log!("Restored subscriptions from the previous run");
+                for pattern in &config.event_patterns {
+                    if !saved.patterns.contains(pattern) {
+                        saved.patterns.push(pattern.clone());
+                    }
+                }
+                saved
+            }
+            None => Subscriptions {
+                patterns: config.event_patterns.clone(),
+                ..Subscriptions::default()
+            },
+        };
+        subscriptions.send_all(client, &mut traffic).await;
 
         // Setup Page
+        let recp_dataset = subscriptions.recp_path.clone();
+        let msgs_dataset = subscriptions.msgs_path.clone();
         let id = client.id();
-        let mut test_page = UiPageManager::new(id.clone(), "Router Test Page");
+        let mut test_page = UiPageManager::new(id.clone(), &config.page_title);
         let mut root = test_page
             .get_element_mut(&UiPath::root())
             .expect("all pages have a root");
         root.set_kind(UiElementKind::Rows);
 
+        if let Some(description) = &config.page_description {
+            root.append_child({
+                let mut element = UiElement::from_string(description);
+                element.set_kind(UiElementKind::Text);
+                element
+            });
+        }
+
         root.append_child({
             let mut element = UiElement::from_string("Add Recp");
             element.set_kind(UiElementKind::TextEntry);
@@ -83,16 +525,105 @@ impl State {
         });
 
         root.append_child({
-            let mut element = UiElement::new(UiElementKind::Rows);
-            element.set_dataset(Some(recp_dataset.clone().resolve(id.clone())));
-            element.append_child({
-                let mut child = UiElement::new(UiElementKind::Text);
-                let mut content = UiElementContent::new();
-                content.add_part(UiElementContentPart::Data(vec![]));
-                child.set_content(content);
+            let mut element = UiElement::from_string("recipients: 0");
+            element.set_kind(UiElementKind::Text);
+            element.set_id("Recp Count");
+            element
+        });
 
-                child
-            });
+        // one line per recipient with its send tally, rendered here rather
+        // than bound to the Recp dataset so the tallies can sit in the rows
+        root.append_child({
+            let mut element = UiElement::from_string("");
+            element.set_kind(UiElementKind::Text);
+            element.set_id("Recp Rows");
+            element
+        });
+
+        root.append_child({
+            let mut element = UiElement::from_string("Import Recps");
+            element.set_kind(UiElementKind::TextEntry);
+            element.set_selectable(true);
+            element.set_id("Import Recps");
+            element
+        });
+
+        root.append_child({
+            let mut element = UiElement::from_string("Clear Recps");
+            element.set_kind(UiElementKind::Button);
+            element.set_selectable(true);
+            element.set_id("Clear Recps");
+            element
+        });
+
+        root.append_child({
+            let mut element = UiElement::from_string("Prune Recps");
+            element.set_kind(UiElementKind::Button);
+            element.set_selectable(true);
+            element.set_id("Prune Recps");
+            element
+        });
+
+        root.append_child({
+            let mut element = UiElement::from_string("Mute (index)");
+            element.set_kind(UiElementKind::TextEntry);
+            element.set_selectable(true);
+            element.set_id("Mute");
+            element
+        });
+
+        root.append_child({
+            let mut element = UiElement::from_string("Tag (index tag)");
+            element.set_kind(UiElementKind::TextEntry);
+            element.set_selectable(true);
+            element.set_id("Tag");
+            element
+        });
+
+        root.append_child({
+            let mut element = UiElement::from_string("Move Recp (from to)");
+            element.set_kind(UiElementKind::TextEntry);
+            element.set_selectable(true);
+            element.set_id("Move Recp");
+            element
+        });
+
+        root.append_child({
+            let mut element = UiElement::from_string("Save Preset (name)");
+            element.set_kind(UiElementKind::TextEntry);
+            element.set_selectable(true);
+            element.set_id("Save Preset");
+            element
+        });
+
+        root.append_child({
+            let mut element = UiElement::from_string("Load Preset (name)");
+            element.set_kind(UiElementKind::TextEntry);
+            element.set_selectable(true);
+            element.set_id("Load Preset");
+            element
+        });
+
+        root.append_child({
+            let mut element = UiElement::from_string("");
+            element.set_kind(UiElementKind::Text);
+            element.set_id("Directory");
+            element
+        });
+
+        root.append_child({
+            let mut element = UiElement::from_string("Set Prop (key=value)");
+            element.set_kind(UiElementKind::TextEntry);
+            element.set_selectable(true);
+            element.set_id("Set Prop");
+            element
+        });
+
+        root.append_child({
+            let mut element = UiElement::from_string("Send To Role (key=value message)");
+            element.set_kind(UiElementKind::TextEntry);
+            element.set_selectable(true);
+            element.set_id("Send To Role");
             element
         });
 
@@ -104,145 +635,2490 @@ impl State {
             element
         });
 
-        root.append_child({
-            let mut element = UiElement::new(UiElementKind::Rows);
-            element.set_dataset(Some(msgs_dataset.clone().resolve(id.clone())));
-            element.append_child({
-                let mut child = UiElement::new(UiElementKind::Text);
-                let mut content = UiElementContent::new();
-                content.add_part(UiElementContentPart::Data(vec![]));
-                child.set_content(content);
+        if config.confirm_sends {
+            root.append_child({
+                let mut element = UiElement::from_string("");
+                element.set_kind(UiElementKind::Text);
+                element.set_id("Send Preview");
+                element
+            });
 
-                child
+            root.append_child({
+                let mut element = UiElement::from_string("Confirm Send");
+                element.set_kind(UiElementKind::Button);
+                element.set_selectable(true);
+                element.set_id("Confirm Send");
+                element
             });
+        }
+
+        root.append_child({
+            let mut element = UiElement::from_string("Send File");
+            element.set_kind(UiElementKind::TextEntry);
+            element.set_selectable(true);
+            element.set_id("Send File");
             element
         });
 
-        drop(root);
+        root.append_child({
+            let mut element = UiElement::from_string("Whoami");
+            element.set_kind(UiElementKind::Button);
+            element.set_selectable(true);
+            element.set_id("Whoami");
+            element
+        });
 
-        test_page.get_changes(); // clear changes to synch, since we are going to send the whole page at first. This
-                                 // Could instead set the initial elements with raw and then recalculate ids
-        let msg = Message::Ui(UiMessage::SetPage(test_page.get_page().clone()));
-        client.send(msg).await;
+        root.append_child({
+            let mut element = UiElement::from_string("Query Caps");
+            element.set_kind(UiElementKind::Button);
+            element.set_selectable(true);
+            element.set_id("Query Caps");
+            element
+        });
 
-        // Create self
-        Self {
-            recps: vec![],
-            msgs: vec![],
-        }
-    }
+        root.append_child({
+            let mut element = UiElement::from_string("Test All");
+            element.set_kind(UiElementKind::Button);
+            element.set_selectable(true);
+            element.set_id("Test All");
+            element
+        });
 
-    async fn msg_handler(&mut self, client: &mut ClientChannel, msg: Message) {
-        match msg {
-            Message::Ui(msg) => self.ui_handler(client, msg).await,
-            Message::Dataset(msg) => self.dataset_handler(client, msg).await,
-            Message::Router(msg) => self.router_handler(client, msg).await,
-            Message::Error(_) => {}
-        }
-    }
+        root.append_child({
+            let mut element = UiElement::from_string("");
+            element.set_kind(UiElementKind::Text);
+            element.set_id("Reachability");
+            element
+        });
 
-    async fn dataset_handler(&mut self, client: &mut ClientChannel, msg: DatasetMessage) {
-        println!("Message: {:?}", msg);
-        if let DatasetMessage::Dataset { path, data } = msg {
-            let recp_dataset = DatasetPath::new_private(vec![String::from("Recp")]);
-            let msgs_dataset = DatasetPath::new_private(vec![String::from("Messages")]);
-            if path == recp_dataset {
-                self.recps = data;
-            } else if path == msgs_dataset {
-                self.msgs = data;
-                if self.msgs.len() > 10 {
-                    let msgs_dataset = DatasetPath::new_private(vec![String::from("Messages")]);
-                    let msg = Message::Dataset(DatasetMessage::DeleteElement {
-                        path: msgs_dataset.clone(),
-                        id: 0,
-                    });
-                    client.send(msg).await;
-                }
-            }
-        }
-    }
+        root.append_child({
+            let mut element = UiElement::from_string("Schedule");
+            element.set_kind(UiElementKind::TextEntry);
+            element.set_selectable(true);
+            element.set_id("Schedule");
+            element
+        });
 
-    async fn ui_handler(&mut self, client: &mut ClientChannel, msg: UiMessage) {
-        match msg {
-            UiMessage::Subscribe => {}
-            UiMessage::Pages(_) => {}
-            UiMessage::GetPage(_) => {}
-            UiMessage::Page(_) => {}
-            UiMessage::UpdateElementsFor(_, _) => {}
-            UiMessage::InputFor(_, _, _, _) => {}
-            UiMessage::SetPage(_) => {}
-            UiMessage::ClearPage => {}
-            UiMessage::UpdateElements(_) => {}
-            UiMessage::Input(element_id, _dataset_ids, change) => {
-                match element_id.as_str() {
-                    "Add Recp" => {
-                        if let UiInput::Text(text) = change {
-                            let recp_dataset = DatasetPath::new_private(vec![String::from("Recp")]);
-                            let data = spider_client::message::DatasetData::String(text);
-                            let msg = Message::Dataset(DatasetMessage::Append {
-                                path: recp_dataset,
-                                data: data,
-                            });
-                            client.send(msg).await;
-                        }
-                    }
-                    "Send Msg" => {
-                        // emit message
-                        if let UiInput::Text(text) = change {
-                            // generate recps from data
-                            let mut recps = vec![];
-                            for recp in &self.recps {
-                                if let DatasetData::String(recp) = recp {
-                                    if let Some(relation) = Relation::peer_from_base_64(recp) {
-                                        recps.push(relation);
-                                    }
-                                }
-                            }
-                            let data = spider_client::message::DatasetData::String(text);
-                            let msg = Message::Router(RouterMessage::SendEvent(
-                                String::from("test_event"),
-                                recps,
-                                data,
-                            ));
-                            client.send(msg).await;
-                        }
-                    }
-                    _ => return,
-                }
-            }
-            UiMessage::Dataset(_, _) => {}
-        }
-    }
+        root.append_child({
+            let mut element = UiElement::from_string("Cancel Scheduled");
+            element.set_kind(UiElementKind::TextEntry);
+            element.set_selectable(true);
+            element.set_id("Cancel Scheduled");
+            element
+        });
 
-    async fn router_handler(&mut self, client: &mut ClientChannel, msg: RouterMessage) {
-        match msg {
-            // Authorization Messages
-            RouterMessage::Pending => {}
-            RouterMessage::ApprovalCode(_) => {}
-            RouterMessage::Approved => {}
-            RouterMessage::Denied => {}
+        root.append_child({
+            let mut element = UiElement::from_string("");
+            element.set_kind(UiElementKind::Text);
+            element.set_id("Scheduled");
+            element
+        });
 
-            // Routing Messages
-            RouterMessage::SendEvent(_, _, _) => {}
-            RouterMessage::Event(name, _, data) => {
-                if name == "test_event" {
-                    let msgs_dataset = DatasetPath::new_private(vec![String::from("Messages")]);
-                    let msg = Message::Dataset(DatasetMessage::Append {
-                        path: msgs_dataset,
-                        data: data,
-                    });
-                    client.send(msg).await;
-                }
-            }
-            RouterMessage::Subscribe(_) => {}
-            RouterMessage::Unsubscribe(_) => {}
+        root.append_child({
+            let mut element = UiElement::from_string("Resend Failed");
+            element.set_kind(UiElementKind::Button);
+            element.set_selectable(true);
+            element.set_id("Resend Failed");
+            element
+        });
 
-            // directory messages
-            RouterMessage::SubscribeDir => {}
-            RouterMessage::UnsubscribeDir => {}
-            RouterMessage::AddIdentity(_) => {}
-            RouterMessage::RemoveIdentity(_) => {}
-            RouterMessage::SetIdentityProperty(_, _) => {}
+        root.append_child({
+            let mut element = UiElement::from_string("");
+            element.set_kind(UiElementKind::Text);
+            element.set_id("Send Status");
+            element
+        });
+
+        root.append_child({
+            let mut element = UiElement::from_string("Pause");
+            element.set_kind(UiElementKind::Button);
+            element.set_selectable(true);
+            element.set_id("Pause");
+            element
+        });
+
+        root.append_child({
+            let mut element = UiElement::from_string("Filter");
+            element.set_kind(UiElementKind::TextEntry);
+            element.set_selectable(true);
+            element.set_id("Filter");
+            element
+        });
+
+        root.append_child({
+            let mut element = UiElement::from_string("");
+            element.set_kind(UiElementKind::Text);
+            element.set_id("Filter Results");
+            element
+        });
+
+        root.append_child({
+            let mut element = UiElement::from_string("");
+            element.set_kind(UiElementKind::Text);
+            element.set_id("Status");
+            element
+        });
+
+        root.append_child({
+            let mut element = UiElement::from_string("sent 0 B, received 0 B");
+            element.set_kind(UiElementKind::Text);
+            element.set_id("Traffic");
+            element
+        });
+
+        root.append_child({
+            let mut element = UiElement::from_string("");
+            element.set_kind(UiElementKind::Text);
+            element.set_id("Feedback");
+            element
+        });
+
+        root.append_child({
+            let mut element = UiElement::from_string("last message: never");
+            element.set_kind(UiElementKind::Text);
+            element.set_id("Last Message");
+            element
+        });
+
+        if config.compact_window.is_some() {
+            root.append_child({
+                let mut element = UiElement::from_string("");
+                element.set_kind(UiElementKind::Text);
+                element.set_id("Msgs Compact");
+                element
+            });
+        } else {
+            root.append_child({
+                let mut element = UiElement::new(UiElementKind::Rows);
+                element.set_dataset(Some(msgs_dataset.clone().resolve(id.clone())));
+                element.set_id("Msgs Rows");
+                element.append_child({
+                    let mut child = UiElement::new(UiElementKind::Text);
+                    child.set_content(template::parse(&config.msg_template));
+
+                    child
+                });
+                element
+            });
+        }
+
+        root.append_child({
+            let mut element = UiElement::from_string("Subscribe Pattern");
+            element.set_kind(UiElementKind::TextEntry);
+            element.set_selectable(true);
+            element.set_id("Subscribe Pattern");
+            element
+        });
+
+        root.append_child({
+            let mut element = UiElement::from_string(&subscriptions.render());
+            element.set_kind(UiElementKind::Text);
+            element.set_id("Subscriptions");
+            element
+        });
+
+        root.append_child({
+            let mut element = UiElement::from_string("Show Raw");
+            element.set_kind(UiElementKind::Button);
+            element.set_selectable(true);
+            element.set_id("Show Raw");
+            element
+        });
+
+        root.append_child({
+            let mut element = UiElement::from_string("");
+            element.set_kind(UiElementKind::Text);
+            element.set_id("Raw Datasets");
+            element
+        });
+
+        root.append_child({
+            let mut element = UiElement::from_string("Latency (index [count])");
+            element.set_kind(UiElementKind::TextEntry);
+            element.set_selectable(true);
+            element.set_id("Latency");
+            element
+        });
+
+        root.append_child({
+            let mut element = UiElement::from_string("");
+            element.set_kind(UiElementKind::Text);
+            element.set_id("Latency Report");
+            element
+        });
+
+        root.append_child({
+            let mut element = UiElement::from_string("Stats");
+            element.set_kind(UiElementKind::Button);
+            element.set_selectable(true);
+            element.set_id("Stats");
+            element
+        });
+
+        root.append_child({
+            let mut element = UiElement::from_string("");
+            element.set_kind(UiElementKind::Text);
+            element.set_id("Stats Chart");
+            element
+        });
+
+        root.append_child({
+            let mut element = UiElement::from_string("Dump Events");
+            element.set_kind(UiElementKind::Button);
+            element.set_selectable(true);
+            element.set_id("Dump Events");
+            element
+        });
+
+        root.append_child({
+            let mut element = UiElement::from_string("Switch Dataset (recp|msgs <name>)");
+            element.set_kind(UiElementKind::TextEntry);
+            element.set_selectable(true);
+            element.set_id("Switch Dataset");
+            element
+        });
+
+        if config.log_unknown_events {
+            root.append_child({
+                let mut element = UiElement::new(UiElementKind::Rows);
+                element.set_dataset(Some(unhandled_dataset().resolve(id.clone())));
+                element.append_child({
+                    let mut child = UiElement::new(UiElementKind::Text);
+                    child.set_content(template::parse("{event}: {text}"));
+                    child
+                });
+                element
+            });
+        }
+
+        drop(root);
+
+        if let Some(path) = &config.page_file {
+            match load_page(path) {
+                Ok(elements) => {
+                    log!("Serving the page from {}", path.display());
+                    test_page = UiPageManager::new(id.clone(), &config.page_title);
+                    let mut root = test_page
+                        .get_element_mut(&UiPath::root())
+                        .expect("all pages have a root");
+                    root.set_kind(UiElementKind::Rows);
+                    for element in elements {
+                        root.append_child(element);
+                    }
+                    drop(root);
+                    for input in Input::ALL {
+                        if test_page.get_by_id_mut(input.id()).is_none() {
+                            log!(Error; "Page file has no {:?} element, its input is unused",
+                                input.id());
+                        }
+                    }
+                }
+                Err(e) => {
+                    log!(Error; "Failed to load page {}, using the built-in page: {}",
+                        path.display(), e);
+                }
+            }
+        }
+
+        test_page.get_changes(); // clear changes to synch, since we are going to send the whole page at first. This
+                                 // Could instead set the initial elements with raw and then recalculate ids
+        if let Some(delay) = config.page_delay {
+            tokio::time::sleep(delay).await;
+        }
+        let msg = Message::Ui(UiMessage::SetPage(test_page.get_page().clone()));
+        traffic.record_sent(&msg);
+        client.send(msg).await;
+
+        // Create self
+        let dedup = Dedup::new(config.dedup_window);
+        let trimmer = Trimmer::new(config.max_msgs);
+        let ring = MessageRing::new(config.ring_size);
+        let cache = config.persist_dir.clone().and_then(|dir| {
+            let passphrase = config.persist_passphrase.clone();
+            match Cache::new(dir, config.persist_format, passphrase) {
+                Ok(cache) => Some(cache),
+                Err(e) => {
+                    log!(Error; "Failed to open the cache, not persisting: {}", e);
+                    None
+                }
+            }
+        });
+        let (recps, msgs) = match &cache {
+            Some(cache) => (cache.load("recps"), cache.load("msgs")),
+            None => (vec![], vec![]),
+        };
+        if !recps.is_empty() || !msgs.is_empty() {
+            log!(
+                "Restored {} recipients and {} messages from cache",
+                recps.len(),
+                msgs.len()
+            );
+        }
+        let mut search = SearchIndex::default();
+        search.sync(&msgs);
+        let sends = SendTracker::new(config.ack_window, config.max_retries, config.retry_backoff);
+        let event_log = config.event_log.as_ref().and_then(|path| {
+            match EventLog::open(path, config.log_rotation()) {
+                Ok(log) => Some(log),
+                Err(e) => {
+                    log!(Error; "Failed to open event log {}: {}", path.display(), e);
+                    None
+                }
+            }
+        });
+        let web = match config.web_port {
+            Some(port) => match web::Mirror::serve(config.web_bind, port).await {
+                Ok(web) => Some(web),
+                Err(e) => {
+                    log!(Error; "Failed to start web mirror on port {}: {}", port, e);
+                    None
+                }
+            },
+            None => None,
+        };
+        if let Some(web) = &web {
+            web.set_recps(recps.iter().map(messages::text).collect());
+            web.set_msgs(msgs.iter().map(messages::text).collect());
+        }
+        let audit = config
+            .audit_log
+            .as_ref()
+            .and_then(|path| match AuditLog::open(path) {
+                Ok(audit) => Some(audit),
+                Err(e) => {
+                    log!(Error; "Failed to open audit log {}: {}", path.display(), e);
+                    None
+                }
+            });
+        let faults = config
+            .inject_faults
+            .clone()
+            .map(|rates| FaultInjector::new(rates, config.fault_delay));
+        let sequencer = config.in_order.map(Sequencer::new);
+        Self {
+            config,
+            page: test_page,
+            recps_len: recps.len(),
+            msgs_len: msgs.len(),
+            recps,
+            msgs,
+            sends,
+            next_recp: 0,
+            last_activity: clock.now(),
+            last_msg_at: None,
+            last_event_at: None,
+            search,
+            filter: None,
+            viewers: HashMap::new(),
+            event_log,
+            audit,
+            recp_stats: RecpStatsTable::default(),
+            paused: false,
+            paused_msgs: vec![],
+            batch: vec![],
+            batch_started: None,
+            unknown_events: HashMap::new(),
+            scheduler: Scheduler::default(),
+            forwarding: false,
+            forwarded: vec![],
+            directory: Directory::default(),
+            msg_ids: MessageIds::default(),
+            dedup,
+            show_raw: false,
+            trimmer,
+            subscriptions,
+            auto_added: vec![],
+            reconciler: Reconciler::default(),
+            ring,
+            cache,
+            web,
+            signer,
+            pending_send: None,
+            queue: SendQueue::default(),
+            ping: None,
+            latency: None,
+            recp_path: recp_dataset,
+            msgs_path: msgs_dataset,
+            denied: false,
+            denied_at: None,
+            directory_dirty: false,
+            directory_shown_at: clock.now(),
+            health_probe: None,
+            last_probe: clock.now(),
+            unhealthy: false,
+            last_prune: clock.now(),
+            last_recp_check: clock.now(),
+            feedback_until: None,
+            clock,
+            page_cleared_at: None,
+            held: VecDeque::new(),
+            faults,
+            delayed: VecDeque::new(),
+            seqs: HashMap::new(),
+            sequencer,
+            traffic,
+        }
+    }
+
+    async fn msg_handler(&mut self, client: &mut ClientChannel, msg: Message) {
+        self.ring.record(format!("{:?}", msg));
+        self.traffic.record_received(&msg);
+        self.last_msg_at = Some(self.clock.now());
+        match msg {
+            Message::Ui(msg) => self.ui_handler(client, msg).await,
+            Message::Dataset(msg) => self.dataset_handler(client, msg).await,
+            Message::Router(msg) => self.router_handler(client, msg).await,
+            Message::Error(e) => {
+                log!(Error; "Error: {:?}", e);
+                if let Some(send) = self.sends.fail_oldest(format!("{:?}", e), self.clock.now()) {
+                    log!(Error; "Send #{} failed", send.id);
+                    self.recp_stats.record_failed(&send.recps);
+                    self.update_send_status(client).await;
+                }
+            }
+        }
+    }
+
+    async fn tick(&mut self, client: &mut ClientChannel) {
+        let now = self.clock.now();
+        for retry in self.sends.pop_due_retries(now) {
+            log!("Retrying send (attempt {})", retry.attempt);
+            self.send_attempt(client, retry.name, retry.recps, retry.data, retry.attempt)
+                .await;
+        }
+        if let Some(rate) = self.config.send_rate {
+            for (text, recps, opts) in self.queue.pop(rate) {
+                self.send_to(client, text, recps, opts.ttl).await;
+            }
+        }
+        self.finish_ping(client, now).await;
+        self.finish_latency(client, now).await;
+        if let Some(window) = self.config.directory_window {
+            if self.directory_dirty && now.duration_since(self.directory_shown_at) >= window {
+                self.update_directory(client).await;
+            }
+        }
+        self.sweep_expired(client).await;
+        if let Some(interval) = self.config.health_check {
+            self.check_health(client, now, interval).await;
+        }
+        if let Some(interval) = self.config.auto_prune {
+            if now.duration_since(self.last_prune) >= interval {
+                self.prune_recps(client).await;
+            }
+        }
+        if let Some(interval) = self.config.recp_check {
+            let due = now.duration_since(self.last_recp_check) >= interval;
+            if due && self.ping.is_none() && !self.recp_relations().is_empty() {
+                self.last_recp_check = now;
+                self.test_all(client).await;
+            }
+        }
+        if self.feedback_until.is_some_and(|until| now >= until) {
+            self.feedback_until = None;
+            self.set_text("Feedback", String::new());
+            self.push_changes(client).await;
+        }
+        if let (Some(delay), Some(at)) = (self.config.restore_page, self.page_cleared_at) {
+            if now.duration_since(at) >= delay {
+                log!("Restoring cleared page");
+                self.send_page(client).await;
+            }
+        }
+        let due = self.scheduler.pop_due(now);
+        if !due.is_empty() {
+            for text in due {
+                self.send_msg(client, text).await;
+            }
+            self.update_scheduled(client).await;
+        }
+        if self.sends.confirm_expired(now) {
+            self.update_send_status(client).await;
+        }
+        self.release_held(client).await;
+        self.release_delayed(client, now).await;
+        if let Some(cache) = &mut self.cache {
+            cache.flush();
+        }
+        if self.traffic.take_changed() {
+            let traffic = self.traffic.render();
+            if let Some(web) = &self.web {
+                web.set_traffic(traffic.clone());
+            }
+            self.set_text("Traffic", traffic);
+            self.push_changes(client).await;
+        }
+        if let Some(sequencer) = &mut self.sequencer {
+            let ready = sequencer.expire(now);
+            if !ready.is_empty() {
+                log!(
+                    "Gave up waiting for missing messages, storing {} held",
+                    ready.len()
+                );
+                self.store_messages(client, ready).await;
+            }
+        }
+        if let (Some(window), Some(at)) = (self.config.batch_window, self.batch_started) {
+            if now.duration_since(at) >= window {
+                self.flush_batch(client).await;
+            }
+        }
+        if let Some(at) = self.last_msg_at {
+            let text = format!("last message: {}s ago", now.duration_since(at).as_secs());
+            self.set_text("Last Message", text);
+            self.push_changes(client).await;
+        }
+        if let (Some(idle), Some(at)) = (self.config.auto_clear_idle, self.last_event_at) {
+            if now.duration_since(at) >= idle {
+                self.last_event_at = None;
+                if self.msgs_len > 0 {
+                    self.clear_msgs(client).await;
+                }
+            }
+        }
+        if let Some(window) = self.config.watchdog {
+            if now.duration_since(self.last_activity) >= window {
+                log!("No activity for {:?}, resubscribing", window);
+                self.subscriptions.send_all(client, &mut self.traffic).await;
+                self.last_activity = now;
+            }
+        }
+    }
+
+    async fn update_send_status(&mut self, client: &mut ClientChannel) {
+        let summary = self.sends.summary();
+        self.set_text("Send Status", summary);
+        let rows = self
+            .recp_stats
+            .render(&self.recp_relations(), self.clock.now());
+        self.set_text("Recp Rows", rows);
+        self.push_changes(client).await;
+    }
+
+    async fn subscribe_pattern(&mut self, client: &mut ClientChannel, pattern: String) {
+        let pattern = pattern.trim().to_string();
+        if pattern.is_empty() {
+            return;
+        }
+        self.subscriptions
+            .add_pattern(client, &mut self.traffic, pattern)
+            .await;
+        self.update_subscriptions(client).await;
+    }
+
+    async fn update_subscriptions(&mut self, client: &mut ClientChannel) {
+        if let Some(dir) = &self.config.persist_dir {
+            self.subscriptions.save(&dir.join(SUBSCRIPTIONS_FILE));
+        }
+        let text = self.subscriptions.render();
+        self.set_text("Subscriptions", text);
+        self.push_changes(client).await;
+    }
+
+    /// Show the untransformed Recp and Messages contents while Show Raw is on.
+    async fn update_raw(&mut self, client: &mut ClientChannel) {
+        let text = if self.show_raw {
+            format!("Recp: {:?}\nMessages: {:?}", self.recps, self.msgs)
+        } else {
+            String::new()
+        };
+        self.set_text("Raw Datasets", text);
+        self.set_text(
+            "Show Raw",
+            String::from(if self.show_raw {
+                "Hide Raw"
+            } else {
+                "Show Raw"
+            }),
+        );
+        self.push_changes(client).await;
+    }
+
+    /// Show the shared filter's results to every viewer, then each viewer
+    /// with its own filter that viewer's results.
+    async fn update_filter(&mut self, client: &mut ClientChannel) {
+        let shared = self.filter_results(self.filter.as_deref());
+        self.set_text("Filter Results", shared.clone());
+        self.push_changes(client).await;
+        let own: Vec<(Relation, String)> = self
+            .viewers
+            .iter()
+            .filter_map(|(viewer, state)| {
+                let query = state.filter.as_deref()?;
+                Some((viewer.clone(), self.filter_results(Some(query))))
+            })
+            .collect();
+        for (viewer, results) in own {
+            self.set_text("Filter Results", results);
+            let changes = self.page.get_changes();
+            let msg = Message::Ui(UiMessage::UpdateElementsFor(viewer, changes));
+            self.traffic.record_sent(&msg);
+            client.send(msg).await;
+        }
+        // late viewers get the shared results with the page
+        self.set_text("Filter Results", shared);
+        self.page.get_changes();
+    }
+
+    fn filter_results(&self, query: Option<&str>) -> String {
+        match query {
+            Some(query) => self
+                .search
+                .query(query)
+                .into_iter()
+                .filter_map(|idx| self.msgs.get(idx))
+                .map(messages::text)
+                .collect::<Vec<_>>()
+                .join("\n"),
+            None => String::new(),
+        }
+    }
+
+    /// Show a short note to the user in the status element and the log.
+    async fn status(&mut self, client: &mut ClientChannel, text: String) {
+        log!("{}", text);
+        self.set_text("Status", text);
+        self.push_changes(client).await;
+    }
+
+    fn set_text(&mut self, id: &str, text: String) {
+        if let Some(mut element) = self.page.get_by_id_mut(id) {
+            element.set_text(text);
+        }
+    }
+
+    /// Send the whole page, so viewers that attached late can render it.
+    async fn send_page(&mut self, client: &mut ClientChannel) {
+        self.page_cleared_at = None;
+        self.page.get_changes(); // the full page already includes them
+        let msg = Message::Ui(UiMessage::SetPage(self.page.get_page().clone()));
+        self.traffic.record_sent(&msg);
+        client.send(msg).await;
+    }
+
+    /// Send any pending page edits to viewers.
+    async fn push_changes(&mut self, client: &mut ClientChannel) {
+        let changes = self.page.get_changes();
+        if !changes.is_empty() {
+            let msg = Message::Ui(UiMessage::UpdateElements(changes));
+            self.traffic.record_sent(&msg);
+            client.send(msg).await;
+        }
+    }
+
+    async fn dataset_handler(&mut self, client: &mut ClientChannel, msg: DatasetMessage) {
+        self.last_activity = self.clock.now();
+        let (path, mut data) = match msg {
+            DatasetMessage::Dataset { path, data } => (path, data),
+            msg => {
+                log!("Message: {:?}", msg);
+                return;
+            }
+        };
+        let len = data.len();
+        let max = self.config.max_dataset_len;
+        if len > max {
+            log!(
+                "Dataset {:?} has {} entries, keeping {} for display",
+                path,
+                len,
+                max
+            );
+        } else {
+            log!("Dataset {:?}: {:?}", path, data);
+        }
+
+        if path == self.recp_path {
+            // keep the head so recipient indices stay valid
+            data.truncate(max);
+            if let Some(cache) = &mut self.cache {
+                cache.save("recps", &data);
+            }
+            if let Some(web) = &self.web {
+                web.set_recps(data.iter().map(messages::text).collect());
+            }
+            self.recps = data;
+            self.recps_len = len;
+            self.set_text("Recp Count", format!("recipients: {}", len));
+            self.push_changes(client).await;
+            self.update_send_status(client).await;
+            self.update_raw(client).await;
+        } else if path == self.msgs_path {
+            let reconciled = self.reconciler.update(&data);
+            // keep the tail so the newest messages stay visible
+            if let Some(cache) = &mut self.cache {
+                cache.save("msgs", &data);
+            }
+            data.drain(..len.saturating_sub(max));
+            if let Some(web) = &self.web {
+                web.set_msgs(data.iter().map(messages::text).collect());
+            }
+            self.msgs = data;
+            self.msgs_len = len;
+            if let Some(window) = self.config.compact_window {
+                self.set_text("Msgs Compact", compact::render(&self.msgs, window));
+            }
+            self.search.sync(&self.msgs);
+            self.update_filter(client).await;
+            self.update_raw(client).await;
+            for _ in 0..self.trimmer.update(self.msgs_len) {
+                let msg = Message::Dataset(DatasetMessage::DeleteElement {
+                    path: self.msgs_path.clone(),
+                    id: 0,
+                });
+                self.traffic.record_sent(&msg);
+                client.send(msg).await;
+            }
+            // duplicate indices are from before the trim deletes in flight
+            let reconciled = reconciled.after_trim(self.trimmer.in_flight());
+            self.apply_reconciled(client, reconciled).await;
+        }
+    }
+
+    /// Resubscribe on a fresh connection. Messages are held back until the
+    /// Messages dataset arrives and can be reconciled.
+    async fn reconnect(&mut self, client: &mut ClientChannel) {
+        self.last_probe = self.clock.now();
+        self.reconciler.begin();
+        self.subscriptions.send_all(client, &mut self.traffic).await;
+        self.send_page(client).await;
+        let recps = self.with_cc(self.active_recps());
+        if self.config.announce_reconnect && !recps.is_empty() {
+            let data = DatasetData::String(self.config.name.clone());
+            self.send_event(client, String::from("reconnected"), recps, data)
+                .await;
+        }
+    }
+
+    async fn apply_reconciled(&mut self, client: &mut ClientChannel, reconciled: Reconciled) {
+        if reconciled.missing.is_empty() && reconciled.duplicates.is_empty() {
+            return;
+        }
+        let (missing, duplicates) = (reconciled.missing.len(), reconciled.duplicates.len());
+        for idx in reconciled.duplicates {
+            let msg = Message::Dataset(DatasetMessage::DeleteElement {
+                path: self.msgs_path.clone(),
+                id: idx,
+            });
+            self.traffic.record_sent(&msg);
+            client.send(msg).await;
+        }
+        for entry in reconciled.missing {
+            self.append_message(client, entry).await;
+        }
+        self.status(
+            client,
+            format!(
+                "Reconnected: restored {} messages, removed {} duplicates",
+                missing, duplicates
+            ),
+        )
+        .await;
+    }
+
+    async fn ui_handler(&mut self, client: &mut ClientChannel, msg: UiMessage) {
+        match msg {
+            UiMessage::Subscribe => self.send_page(client).await,
+            UiMessage::Pages(_) => {}
+            UiMessage::GetPage(_) => {
+                log!("Answering a page request");
+                let msg = Message::Ui(UiMessage::Page(self.page.get_page().clone()));
+                self.traffic.record_sent(&msg);
+                client.send(msg).await;
+            }
+            UiMessage::Page(_) => log!("Received a page; this router only serves its own"),
+            UiMessage::UpdateElementsFor(_, _) => {}
+            UiMessage::InputFor(viewer, element_id, _dataset_ids, change) => {
+                if let Some(audit) = &mut self.audit {
+                    audit.record(&element_id, Some(&viewer), &change);
+                }
+                if !self.viewers.contains_key(&viewer) {
+                    if self.viewers.len() >= self.config.max_viewers {
+                        log!(
+                            "Ignoring input from {}: viewer limit reached",
+                            relation::display(&viewer)
+                        );
+                        return;
+                    }
+                    self.viewers.insert(viewer.clone(), ViewerState::default());
+                }
+                if element_id != "Filter" {
+                    self.input_handler(client, element_id, change).await;
+                } else if let UiInput::Text(text) = change {
+                    let text = text.trim();
+                    if let Some(state) = self.viewers.get_mut(&viewer) {
+                        state.filter = (!text.is_empty()).then(|| text.to_string());
+                    }
+                    self.update_filter(client).await;
+                }
+            }
+            UiMessage::SetPage(_) => {}
+            UiMessage::ClearPage => {
+                log!("Page cleared by a viewer");
+                self.page_cleared_at = Some(self.clock.now());
+            }
+            UiMessage::UpdateElements(_) => {}
+            UiMessage::Input(element_id, _dataset_ids, change) => {
+                if let Some(audit) = &mut self.audit {
+                    audit.record(&element_id, None, &change);
+                }
+                self.input_handler(client, element_id, change).await;
+            }
+            UiMessage::Dataset(_, _) => {}
+        }
+    }
+
+    async fn input_handler(
+        &mut self,
+        client: &mut ClientChannel,
+        element_id: String,
+        change: UiInput,
+    ) {
+        let Some(input) = Input::from_id(&element_id) else {
+            return;
+        };
+        match input {
+            Input::AddRecp => {
+                if let UiInput::Text(text) = change {
+                    self.add_recp(client, text).await;
+                }
+            }
+            Input::SavePreset => {
+                if let UiInput::Text(text) = change {
+                    self.save_preset(client, &text).await;
+                }
+            }
+            Input::LoadPreset => {
+                if let UiInput::Text(text) = change {
+                    self.load_preset(client, &text).await;
+                }
+            }
+            Input::Tag => {
+                if let UiInput::Text(text) = change {
+                    self.toggle_tag(client, &text).await;
+                }
+            }
+            Input::MoveRecp => {
+                if let UiInput::Text(text) = change {
+                    self.move_recp(client, &text).await;
+                }
+            }
+            Input::Mute => {
+                if let UiInput::Text(text) = change {
+                    self.toggle_mute(client, &text).await;
+                }
+            }
+            Input::PruneRecps => {
+                if let UiInput::Click = change {
+                    self.prune_recps(client).await;
+                }
+            }
+            Input::ClearRecps => {
+                if let UiInput::Click = change {
+                    self.clear_recps(client).await;
+                }
+            }
+            Input::ImportRecps => {
+                if let UiInput::Text(text) = change {
+                    self.import_recps(client, &text).await;
+                }
+            }
+            Input::SetProp => {
+                if let UiInput::Text(text) = change {
+                    self.set_prop(client, &text).await;
+                }
+            }
+            Input::SendMsg => {
+                // emit message
+                if let UiInput::Text(text) = change {
+                    self.send_input(client, text).await;
+                }
+            }
+            Input::SendToRole => {
+                if let UiInput::Text(text) = change {
+                    self.send_to_role(client, &text).await;
+                }
+            }
+            Input::ConfirmSend => {
+                if let UiInput::Click = change {
+                    self.confirm_send(client).await;
+                }
+            }
+            Input::SendFile => {
+                if let UiInput::Text(text) = change {
+                    self.send_file(client, &text).await;
+                }
+            }
+            Input::Whoami => {
+                if let UiInput::Click = change {
+                    self.send_whoami(client).await;
+                }
+            }
+            Input::QueryCaps => {
+                if let UiInput::Click = change {
+                    self.query_caps(client).await;
+                }
+            }
+            Input::TestAll => {
+                if let UiInput::Click = change {
+                    self.test_all(client).await;
+                }
+            }
+            Input::Schedule => {
+                if let UiInput::Text(text) = change {
+                    self.schedule(client, &text).await;
+                }
+            }
+            Input::CancelScheduled => {
+                if let UiInput::Text(text) = change {
+                    self.cancel_scheduled(client, &text).await;
+                }
+            }
+            Input::SubscribePattern => {
+                if let UiInput::Text(text) = change {
+                    self.subscribe_pattern(client, text).await;
+                }
+            }
+            Input::ShowRaw => {
+                if let UiInput::Click = change {
+                    self.show_raw = !self.show_raw;
+                    self.update_raw(client).await;
+                }
+            }
+            Input::SwitchDataset => {
+                if let UiInput::Text(text) = change {
+                    self.switch_dataset(client, &text).await;
+                }
+            }
+            Input::Latency => {
+                if let UiInput::Text(text) = change {
+                    self.measure_latency(client, &text).await;
+                }
+            }
+            Input::Stats => {
+                if let UiInput::Click = change {
+                    self.show_stats(client).await;
+                }
+            }
+            Input::DumpEvents => {
+                if let UiInput::Click = change {
+                    self.dump_events(client).await;
+                }
+            }
+            Input::Pause => {
+                if let UiInput::Click = change {
+                    self.toggle_pause(client).await;
+                }
+            }
+            Input::Filter => {
+                if let UiInput::Text(text) = change {
+                    let text = text.trim();
+                    self.filter = (!text.is_empty()).then(|| text.to_string());
+                    self.update_filter(client).await;
+                }
+            }
+            Input::ResendFailed => {
+                if let UiInput::Click = change {
+                    self.resend_failed(client).await;
+                }
+            }
+        }
+        self.acknowledge_input(client, &element_id).await;
+    }
+
+    async fn acknowledge_input(&mut self, client: &mut ClientChannel, element_id: &str) {
+        self.set_text("Feedback", format!("{} \u{2713}", element_id));
+        self.feedback_until = Some(self.clock.now() + FEEDBACK_TIME);
+        self.push_changes(client).await;
+    }
+
+    async fn command_handler(&mut self, client: &mut ClientChannel, cmd: Command) {
+        match cmd {
+            Command::Add(text) => self.add_recp(client, text).await,
+            Command::Remove(idx) => self.remove_recp(client, idx).await,
+            Command::Mute(idx) => self.toggle_mute(client, &idx.to_string()).await,
+            Command::Move(input) => self.move_recp(client, &input).await,
+            Command::Tag(input) => self.toggle_tag(client, &input).await,
+            Command::Send(text) => self.send_input(client, text).await,
+            Command::Resend => self.resend_failed(client).await,
+            Command::Confirm => self.confirm_send(client).await,
+            Command::Pause => self.toggle_pause(client).await,
+            Command::Whoami => self.send_whoami(client).await,
+            Command::Caps => self.query_caps(client).await,
+            Command::TestAll => self.test_all(client).await,
+            Command::SendFile(path) => self.send_file(client, &path).await,
+            Command::Import(pattern) => self.import_recps(client, &pattern).await,
+            Command::Schedule(input) => self.schedule(client, &input).await,
+            Command::Cancel(input) => self.cancel_scheduled(client, &input).await,
+            Command::ClearRecps => self.clear_recps(client).await,
+            Command::SavePreset(name) => self.save_preset(client, &name).await,
+            Command::LoadPreset(name) => self.load_preset(client, &name).await,
+            Command::Recps => {
+                for (idx, recp) in self.recps.iter().enumerate() {
+                    log!("{}: {:?}", idx, recp);
+                }
+                log!("{} recipients", self.recps_len);
+            }
+            Command::Msgs => {
+                for (idx, msg) in self.msgs.iter().enumerate() {
+                    log!("{}: {:?}", idx, msg);
+                }
+                log!("{} messages", self.msgs_len);
+            }
+            Command::Dump => self.dump_events(client).await,
+            Command::Stats => self.show_stats(client).await,
+            Command::Switch(input) => self.switch_dataset(client, &input).await,
+            Command::Quit => {}
+        }
+    }
+
+    /// Rebind the Recp or Messages rows to another dataset, given as
+    /// `recp <name>` or `msgs <name>` with `/` between path parts.
+    async fn switch_dataset(&mut self, client: &mut ClientChannel, input: &str) {
+        let (which, name) = input.trim().split_once(' ').unwrap_or((input.trim(), ""));
+        let parts: Vec<String> = name
+            .split('/')
+            .map(str::trim)
+            .filter(|part| !part.is_empty())
+            .map(String::from)
+            .collect();
+        if parts.is_empty() {
+            self.status(client, String::from("usage: recp|msgs <name>"))
+                .await;
+            return;
+        }
+        let path = DatasetPath::new_private(parts);
+        let (old, bound) = match which {
+            "recp" => {
+                self.subscriptions.recp_path = path.clone();
+                (std::mem::replace(&mut self.recp_path, path.clone()), false)
+            }
+            "msgs" => {
+                self.trimmer = Trimmer::new(self.config.max_msgs);
+                self.subscriptions.msgs_path = path.clone();
+                (std::mem::replace(&mut self.msgs_path, path.clone()), true)
+            }
+            _ => {
+                self.status(
+                    client,
+                    format!("unknown dataset {:?}, expected recp or msgs", which),
+                )
+                .await;
+                return;
+            }
+        };
+        let msg = Message::Dataset(DatasetMessage::Unsubscribe { path: old.clone() });
+        self.traffic.record_sent(&msg);
+        client.send(msg).await;
+        let msg = Message::Dataset(DatasetMessage::Subscribe { path: path.clone() });
+        self.traffic.record_sent(&msg);
+        client.send(msg).await;
+        self.subscriptions
+            .datasets
+            .retain(|dataset| dataset != &old);
+        self.subscriptions.datasets.push(path.clone());
+        // Recp Rows is rendered from the dataset rather than bound to it
+        if bound {
+            if let Some(mut element) = self.page.get_by_id_mut("Msgs Rows") {
+                element.set_dataset(Some(path.clone().resolve(client.id().clone())));
+            }
+        }
+        self.update_subscriptions(client).await;
+        self.status(client, format!("{} rows now show {:?}", which, path))
+            .await;
+    }
+
+    /// Chart the sizes and arrival gaps of the stored messages.
+    async fn show_stats(&mut self, client: &mut ClientChannel) {
+        let chart = histogram::render(&self.msgs);
+        log!("{}", chart);
+        self.set_text("Stats Chart", chart);
+        self.push_changes(client).await;
+    }
+
+    /// Write the recently received raw messages to the dump file.
+    async fn dump_events(&mut self, client: &mut ClientChannel) {
+        let path = self.config.dump_path.clone();
+        let text = match tokio::fs::write(&path, self.ring.render()).await {
+            Ok(()) => format!("Dumped {} messages to {}", self.ring.len(), path.display()),
+            Err(e) => format!("Failed to write {}: {}", path.display(), e),
+        };
+        self.status(client, text).await;
+    }
+
+    async fn add_recp(&mut self, client: &mut ClientChannel, text: String) {
+        let text = match sanitize::sanitize(&text, self.config.sanitize) {
+            Ok(text) => text,
+            Err(e) => {
+                self.status(client, format!("Rejected recipient: {}", e))
+                    .await;
+                return;
+            }
+        };
+        let relation = match relation::parse(&text) {
+            Ok(relation) => relation,
+            Err(e) => {
+                self.status(client, format!("Rejected recipient: {}", e))
+                    .await;
+                return;
+            }
+        };
+        let msg = Message::Dataset(DatasetMessage::Append {
+            path: self.recp_path.clone(),
+            data: DatasetData::String(relation::display(&relation)),
+        });
+        self.traffic.record_sent(&msg);
+        client.send(msg).await;
+    }
+
+    /// Add a message sender to the Recp list if it is not already there.
+    async fn auto_add_sender(&mut self, client: &mut ClientChannel, sender: &Relation) {
+        // the Recp echo may not have arrived yet for senders added earlier
+        if self.auto_added.contains(sender) || self.recp_relations().contains(sender) {
+            return;
+        }
+        self.auto_added.push(sender.clone());
+        log!("Adding sender {} as a recipient", relation::display(sender));
+        self.add_recp(client, relation::display(sender)).await;
+    }
+
+    /// Add every directory identity whose name matches `pattern` that is not
+    /// already a recipient.
+    async fn import_recps(&mut self, client: &mut ClientChannel, pattern: &str) {
+        let mut known = self.recp_relations();
+        let mut added = 0;
+        for identity in self.directory.find_by_name(pattern.trim()) {
+            if known.contains(&identity.relation) {
+                continue;
+            }
+            known.push(identity.relation.clone());
+            let msg = Message::Dataset(DatasetMessage::Append {
+                path: self.recp_path.clone(),
+                data: DatasetData::String(relation::display(&identity.relation)),
+            });
+            self.traffic.record_sent(&msg);
+            client.send(msg).await;
+            added += 1;
+        }
+        self.status(client, format!("Imported {} recipients", added))
+            .await;
+    }
+
+    /// Show a directory change now, or leave it for the tick to batch with
+    /// others under `--directory-window`.
+    async fn directory_changed(&mut self, client: &mut ClientChannel) {
+        match self.config.directory_window {
+            Some(_) => self.directory_dirty = true,
+            None => self.update_directory(client).await,
+        }
+    }
+
+    async fn update_directory(&mut self, client: &mut ClientChannel) {
+        self.directory_dirty = false;
+        self.directory_shown_at = self.clock.now();
+        let text = self.directory.render();
+        self.set_text("Directory", text);
+        self.push_changes(client).await;
+    }
+
+    /// Publish an identity property given as `key=value`.
+    async fn set_prop(&mut self, client: &mut ClientChannel, text: &str) {
+        let (key, value) = match config::parse_prop(text) {
+            Ok(prop) => prop,
+            Err(e) => {
+                self.status(client, format!("Rejected property: {}", e))
+                    .await;
+                return;
+            }
+        };
+        let msg = RouterMessage::SetIdentityProperty(key.clone(), value.clone());
+        let msg = Message::Router(msg);
+        self.traffic.record_sent(&msg);
+        client.send(msg).await;
+        self.status(client, format!("Set identity property {} = {}", key, value))
+            .await;
+    }
+
+    /// Send to every directory identity with a property, from input of the
+    /// form `key=value message`.
+    async fn send_to_role(&mut self, client: &mut ClientChannel, text: &str) {
+        let (filter, text) = text.trim().split_once(' ').unwrap_or((text.trim(), ""));
+        let (key, value) = match config::parse_prop(filter) {
+            Ok(prop) => prop,
+            Err(e) => {
+                self.status(client, format!("Rejected filter: {}", e)).await;
+                return;
+            }
+        };
+        let text = text.trim();
+        if text.is_empty() {
+            self.status(client, String::from("usage: key=value message"))
+                .await;
+            return;
+        }
+        let recps: Vec<Relation> = self
+            .directory
+            .find_by_property(&key, &value)
+            .into_iter()
+            .map(|identity| identity.relation.clone())
+            .collect();
+        if recps.is_empty() {
+            self.status(client, format!("No identities with {} = {}", key, value))
+                .await;
+            return;
+        }
+        let recps = self.with_cc(recps);
+        self.preview_or_send(client, text.to_string(), recps, SendOptions::default())
+            .await;
+    }
+
+    async fn save_preset(&mut self, client: &mut ClientChannel, name: &str) {
+        let recps = self.recp_relations();
+        let text = match presets::save(&self.config.preset_dir, name, &recps) {
+            Ok(path) => format!("Saved {} recipients to {}", recps.len(), path.display()),
+            Err(e) => format!("Failed to save preset: {}", e),
+        };
+        self.status(client, text).await;
+    }
+
+    /// Replace the Recp dataset with a saved preset.
+    async fn load_preset(&mut self, client: &mut ClientChannel, name: &str) {
+        let recps = match presets::load(&self.config.preset_dir, name) {
+            Ok(recps) => recps,
+            Err(e) => {
+                self.status(client, format!("Failed to load preset: {}", e))
+                    .await;
+                return;
+            }
+        };
+        if self.recps_len > 0 {
+            self.clear_recps(client).await;
+        }
+        for relation in &recps {
+            let msg = Message::Dataset(DatasetMessage::Append {
+                path: self.recp_path.clone(),
+                data: DatasetData::String(relation::display(relation)),
+            });
+            self.traffic.record_sent(&msg);
+            client.send(msg).await;
+        }
+        self.status(
+            client,
+            format!("Loaded {} recipients from {}", recps.len(), name.trim()),
+        )
+        .await;
+    }
+
+    /// Delete recipients that are not in the directory, or cannot be
+    /// parsed. Skipped while the directory is empty, since that usually
+    /// means it has not arrived yet.
+    async fn prune_recps(&mut self, client: &mut ClientChannel) {
+        self.last_prune = self.clock.now();
+        if self.directory.is_empty() {
+            self.status(client, String::from("Directory is empty, not pruning"))
+                .await;
+            return;
+        }
+        let stale: Vec<usize> = (0..self.recps.len())
+            .filter(|&idx| match &self.recps[idx] {
+                DatasetData::String(recp) => match relation::parse(recp) {
+                    Ok(relation) => !self.directory.contains(&relation),
+                    Err(_) => true,
+                },
+                _ => true,
+            })
+            .collect();
+        // highest first so the remaining indices stay valid
+        for &idx in stale.iter().rev() {
+            let msg = Message::Dataset(DatasetMessage::DeleteElement {
+                path: self.recp_path.clone(),
+                id: idx,
+            });
+            self.traffic.record_sent(&msg);
+            client.send(msg).await;
+            self.recps.remove(idx);
+        }
+        self.recps_len -= stale.len();
+        self.status(client, format!("Pruned {} recipients", stale.len()))
+            .await;
+    }
+
+    /// Add or remove a tag on a recipient, from input `index tag`.
+    async fn toggle_tag(&mut self, client: &mut ClientChannel, input: &str) {
+        let relation = match input.trim().split_once(' ') {
+            Some((idx, tag)) if !tag.trim().is_empty() => match idx.parse::<usize>() {
+                Ok(idx) => match self.recps.get(idx) {
+                    Some(DatasetData::String(recp)) => {
+                        relation::parse(recp).map(|relation| (relation, tag.trim()))
+                    }
+                    _ => Err(format!("no recipient at index {}", idx)),
+                },
+                Err(_) => Err(format!("Tag expects an index, got {:?}", idx)),
+            },
+            _ => Err(String::from("usage: <index> <tag>")),
+        };
+        let (relation, tag) = match relation {
+            Ok(found) => found,
+            Err(e) => {
+                self.status(client, e).await;
+                return;
+            }
+        };
+        let verb = if self.recp_stats.toggle_tag(&relation, tag) {
+            "Tagged"
+        } else {
+            "Untagged"
+        };
+        let label = format!("[#{}]", tag);
+        log!(
+            "{} {} {}",
+            verb,
+            relation::display(&relation),
+            output::paint(output::Tone::Label, label.clone())
+        );
+        self.set_text(
+            "Status",
+            format!("{} {} {}", verb, relation::display(&relation), label),
+        );
+        self.update_send_status(client).await;
+    }
+
+    /// Move the recipient at one index to another, from input `from to`.
+    ///
+    /// Datasets have no insert, so every entry from the lower index onward
+    /// is deleted and appended again in the new order.
+    async fn move_recp(&mut self, client: &mut ClientChannel, input: &str) {
+        let indices: Vec<usize> = input
+            .split_whitespace()
+            .filter_map(|part| part.parse().ok())
+            .collect();
+        let (from, to) = match indices[..] {
+            [from, to] if from < self.recps.len() && to < self.recps.len() => (from, to),
+            [_, _] => {
+                self.status(client, String::from("Move Recp index out of range"))
+                    .await;
+                return;
+            }
+            _ => {
+                self.status(client, String::from("usage: <from> <to>"))
+                    .await;
+                return;
+            }
+        };
+        if self.recps_len > self.recps.len() {
+            self.status(
+                client,
+                String::from("Recp list is longer than --max-dataset-len, not reordering"),
+            )
+            .await;
+            return;
+        }
+        if from == to {
+            return;
+        }
+        let low = from.min(to);
+        let mut tail = self.recps.split_off(low);
+        let moved = tail.remove(from - low);
+        tail.insert(to - low, moved);
+        for id in (low..low + tail.len()).rev() {
+            let msg = Message::Dataset(DatasetMessage::DeleteElement {
+                path: self.recp_path.clone(),
+                id,
+            });
+            self.traffic.record_sent(&msg);
+            client.send(msg).await;
+        }
+        for data in &tail {
+            let msg = Message::Dataset(DatasetMessage::Append {
+                path: self.recp_path.clone(),
+                data: data.clone(),
+            });
+            self.traffic.record_sent(&msg);
+            client.send(msg).await;
+        }
+        // the echo replaces this, but sends before it should see the new order
+        self.recps.extend(tail);
+        self.status(client, format!("Moved recipient {} to {}", from, to))
+            .await;
+    }
+
+    async fn toggle_mute(&mut self, client: &mut ClientChannel, input: &str) {
+        let relation = match input.trim().parse::<usize>() {
+            Ok(idx) => match self.recps.get(idx) {
+                Some(DatasetData::String(recp)) => relation::parse(recp),
+                _ => Err(format!("no recipient at index {}", idx)),
+            },
+            Err(_) => Err(format!("Mute expects an index, got {:?}", input)),
+        };
+        let relation = match relation {
+            Ok(relation) => relation,
+            Err(e) => {
+                self.status(client, e).await;
+                return;
+            }
+        };
+        let muted = self.recp_stats.toggle_muted(&relation);
+        self.update_send_status(client).await;
+        let state = if muted { "Muted" } else { "Unmuted" };
+        self.status(
+            client,
+            format!("{} {}", state, relation::display(&relation)),
+        )
+        .await;
+    }
+
+    async fn remove_recp(&mut self, client: &mut ClientChannel, idx: usize) {
+        if idx >= self.recps_len {
+            log!("No recipient at index {}", idx);
+            return;
+        }
+        let msg = Message::Dataset(DatasetMessage::DeleteElement {
+            path: self.recp_path.clone(),
+            id: idx,
+        });
+        self.traffic.record_sent(&msg);
+        client.send(msg).await;
+    }
+
+    /// Handle text from the Send field: a slash-command or a plain message.
+    async fn send_input(&mut self, client: &mut ClientChannel, text: String) {
+        match slash::parse(&text) {
+            None => match mention::parse(&text) {
+                None => {
+                    let recps = self.recipients();
+                    self.preview_or_send(client, text, recps, SendOptions::default())
+                        .await;
+                }
+                Some(Ok((recps, text))) => {
+                    let recps = self.with_cc(recps);
+                    self.preview_or_send(client, text, recps, SendOptions::default())
+                        .await;
+                }
+                Some(Err(e)) => self.status(client, e).await,
+            },
+            Some(Ok(SlashCommand::To(idx, text))) => {
+                let relation = match self.recps.get(idx) {
+                    Some(DatasetData::String(recp)) => relation::parse(recp),
+                    _ => Err(format!("no recipient at index {}", idx)),
+                };
+                match relation {
+                    Ok(relation) => {
+                        let recps = self.with_cc(vec![relation]);
+                        self.preview_or_send(client, text, recps, SendOptions::default())
+                            .await;
+                    }
+                    Err(e) => self.status(client, e).await,
+                }
+            }
+            Some(Ok(SlashCommand::All(text))) => {
+                let recps = self.with_cc(self.active_recps());
+                self.preview_or_send(client, text, recps, SendOptions::default())
+                    .await;
+            }
+            Some(Ok(SlashCommand::Urgent(text))) => {
+                let recps = self.recipients();
+                let opts = SendOptions {
+                    priority: Priority::Urgent,
+                    ..SendOptions::default()
+                };
+                self.preview_or_send(client, text, recps, opts).await;
+            }
+            Some(Ok(SlashCommand::Ttl(ttl, text))) => {
+                let recps = self.recipients();
+                let opts = SendOptions {
+                    ttl: Some(ttl),
+                    ..SendOptions::default()
+                };
+                self.preview_or_send(client, text, recps, opts).await;
+            }
+            Some(Ok(SlashCommand::Tag(tag, text))) => {
+                let recps: Vec<Relation> = self
+                    .active_recps()
+                    .into_iter()
+                    .filter(|recp| self.recp_stats.has_tag(recp, &tag))
+                    .collect();
+                if recps.is_empty() {
+                    self.status(client, format!("No recipients tagged {}", tag))
+                        .await;
+                    return;
+                }
+                let recps = self.with_cc(recps);
+                self.preview_or_send(client, text, recps, SendOptions::default())
+                    .await;
+            }
+            Some(Ok(SlashCommand::Clear)) => self.clear_msgs(client).await,
+            Some(Err(e)) => self.status(client, e).await,
+        }
+    }
+
+    /// Send typed text to `recps`, or with `--confirm-sends` hold it and show
+    /// who it would go to until Confirm Send is pressed.
+    async fn preview_or_send(
+        &mut self,
+        client: &mut ClientChannel,
+        text: String,
+        recps: Vec<Relation>,
+        opts: SendOptions,
+    ) {
+        if !self.config.confirm_sends {
+            self.send_text(client, text, recps, opts).await;
+            return;
+        }
+        let mut lines = vec![format!("{:?} would go to:", text)];
+        for recp in &recps {
+            match self.directory.name_of(recp) {
+                Some(name) => lines.push(format!("{} ({})", name, relation::display(recp))),
+                None => lines.push(relation::display(recp)),
+            }
+        }
+        if recps.is_empty() {
+            lines.push(String::from("no recipients"));
+        }
+        let preview = lines.join("\n");
+        log!("{}", preview);
+        self.set_text("Send Preview", preview);
+        self.pending_send = Some((text, recps, opts));
+        self.push_changes(client).await;
+    }
+
+    async fn confirm_send(&mut self, client: &mut ClientChannel) {
+        let Some((text, recps, opts)) = self.pending_send.take() else {
+            self.status(client, String::from("Nothing to confirm"))
+                .await;
+            return;
+        };
+        self.set_text("Send Preview", String::new());
+        self.send_text(client, text, recps, opts).await;
+    }
+
+    async fn send_msg(&mut self, client: &mut ClientChannel, text: String) {
+        let recps = self.recipients();
+        self.send_text(client, text, recps, SendOptions::default())
+            .await;
+    }
+
+    /// Clean up typed text and send it to `recps`.
+    async fn send_text(
+        &mut self,
+        client: &mut ClientChannel,
+        text: String,
+        recps: Vec<Relation>,
+        opts: SendOptions,
+    ) {
+        let text = sanitize::expand_newlines(&text);
+        let text = match sanitize::sanitize(&text, self.config.sanitize) {
+            Ok(text) => text,
+            Err(e) => {
+                self.status(client, format!("Message not sent: {}", e))
+                    .await;
+                return;
+            }
+        };
+        let text = format!(
+            "{}{}{}",
+            self.config.msg_prefix, text, self.config.msg_suffix
+        );
+        let text = self.config.transform_out.apply(text);
+        if self.config.send_rate.is_some() {
+            self.queue.push(opts, text, recps);
+            log!("{} sends queued", self.queue.len());
+        } else {
+            self.send_to(client, text, recps, opts.ttl).await;
+        }
+    }
+
+    /// Re-send the last message to the recipients that have not sent a
+    /// receipt for it. Each copy keeps the payload it was first sent with.
+    async fn resend_failed(&mut self, client: &mut ClientChannel) {
+        let group = self.sends.last_group();
+        let retry = if group.is_empty() {
+            Err("Nothing to resend")
+        } else if group
+            .iter()
+            .any(|send| matches!(send.status, SendStatus::Sending))
+        {
+            Err("Last send is still in flight")
+        } else {
+            let resends: Vec<_> = group
+                .iter()
+                .map(|send| (send.name.clone(), send.unacked(), send.data.clone()))
+                .filter(|(_, recps, _)| !recps.is_empty())
+                .collect();
+            if resends.is_empty() {
+                Err("Every recipient received the last send")
+            } else {
+                Ok(resends)
+            }
+        };
+        match retry {
+            Ok(resends) => {
+                for (name, recps, data) in resends {
+                    self.send_attempt(client, name, recps, data, 0).await;
+                }
+            }
+            Err(note) => self.status(client, note.to_string()).await,
+        }
+    }
+
+    async fn send_to(
+        &mut self,
+        client: &mut ClientChannel,
+        text: String,
+        recps: Vec<Relation>,
+        ttl: Option<Duration>,
+    ) {
+        let mut data = messages::outbound(text, self.msg_ids.next());
+        if let Some(ttl) = ttl {
+            data = messages::with_ttl(data, ttl);
+        }
+        self.send_event(client, String::from("test_event"), recps, data)
+            .await;
+    }
+
+    async fn send_event(
+        &mut self,
+        client: &mut ClientChannel,
+        name: String,
+        recps: Vec<Relation>,
+        data: DatasetData,
+    ) {
+        if recps.is_empty() {
+            self.status(client, format!("Not sending {}: no recipients", name))
+                .await;
+            return;
+        }
+        for (recps, data) in self.stamp(&name, recps, data) {
+            self.send_attempt(client, name.clone(), recps, data, 0)
+                .await;
+        }
+    }
+
+    /// Hand one already stamped event to the router and track it. Retries
+    /// and held sends come back through here with the payload unchanged, so
+    /// they keep their original `seq`.
+    async fn send_attempt(
+        &mut self,
+        client: &mut ClientChannel,
+        name: String,
+        recps: Vec<Relation>,
+        data: DatasetData,
+        attempt: u32,
+    ) {
+        if let Some(limit) = self.config.max_in_flight {
+            if self.sends.in_flight() >= limit {
+                self.held.push_back((name, recps, data, attempt));
+                log!("{} sends held", self.held.len());
+                return;
+            }
+        }
+        let now = self.clock.now();
+        self.sends
+            .start(name.clone(), recps.clone(), data.clone(), attempt, now);
+        self.recp_stats.record_sent(&recps, now);
+        if let Some(log) = &mut self.event_log {
+            log.record(&name, &recps, &data);
+        }
+        let fault = match &mut self.faults {
+            Some(faults) => faults.roll(),
+            None => Fault::None,
+        };
+        let data = match (&fault, &mut self.faults) {
+            (Fault::Corrupt, Some(faults)) => {
+                log!("Injected fault: corrupted {}", name);
+                faults.corrupt(data)
+            }
+            _ => data,
+        };
+        let msg = Message::Router(RouterMessage::SendEvent(name.clone(), recps, data));
+        match fault {
+            Fault::Drop => log!("Injected fault: dropped {}", name),
+            Fault::Delay(by) => {
+                log!("Injected fault: delayed {} by {:?}", name, by);
+                self.delayed.push_back((now + by, msg));
+            }
+            Fault::None | Fault::Corrupt => {
+                self.traffic.record_sent(&msg);
+                self.time_ping(&msg, now);
+                client.send(msg).await;
+            }
+        }
+        self.update_send_status(client).await;
+    }
+
+    /// Split a test event into one send per recipient, each numbered in that
+    /// recipient's own sequence for receivers running `--in-order`, then
+    /// signed. Other events go to every recipient in one send. Called once
+    /// per logical send, never for retries.
+    fn stamp(
+        &mut self,
+        name: &str,
+        recps: Vec<Relation>,
+        data: DatasetData,
+    ) -> Vec<(Vec<Relation>, DatasetData)> {
+        if name != "test_event" {
+            return vec![(recps, data)];
+        }
+        recps
+            .into_iter()
+            .map(|recp| {
+                let seq = self.seqs.entry(recp.clone()).or_insert(0);
+                let mut data = messages::with_seq(data.clone(), *seq);
+                *seq += 1;
+                if let Some(signer) = &self.signer {
+                    data = signer.sign(data);
+                }
+                (vec![recp], data)
+            })
+            .collect()
+    }
+
+    /// Append received entries, or hold them while paused.
+    async fn store_messages(&mut self, client: &mut ClientChannel, entries: Vec<DatasetData>) {
+        for entry in entries {
+            if self.paused {
+                self.paused_msgs.push(entry);
+            } else {
+                self.append_message(client, entry).await;
+            }
+        }
+    }
+
+    /// Send the messages held back by injected delays that are now due.
+    async fn release_delayed(&mut self, client: &mut ClientChannel, now: Instant) {
+        while self.delayed.front().is_some_and(|(due, _)| *due <= now) {
+            if let Some((_, msg)) = self.delayed.pop_front() {
+                self.traffic.record_sent(&msg);
+                self.time_ping(&msg, now);
+                client.send(msg).await;
+            }
+        }
+    }
+
+    /// Start timing a latency ping as it leaves.
+    fn time_ping(&mut self, msg: &Message, now: Instant) {
+        let (Some(run), Message::Router(RouterMessage::SendEvent(name, _, data))) =
+            (&mut self.latency, msg)
+        else {
+            return;
+        };
+        if name == "ping" {
+            if let Some(id) = messages::id(data) {
+                run.mark_sent(id, now);
+            }
+        }
+    }
+
+    /// Start held sends while there is room under `--max-in-flight`.
+    async fn release_held(&mut self, client: &mut ClientChannel) {
+        let limit = self.config.max_in_flight.unwrap_or(usize::MAX);
+        while self.sends.in_flight() < limit {
+            let Some((name, recps, data, attempt)) = self.held.pop_front() else {
+                break;
+            };
+            self.send_attempt(client, name, recps, data, attempt).await;
+        }
+    }
+
+    /// Relations in the Recp dataset, in order.
+    fn recp_relations(&self) -> Vec<Relation> {
+        let mut recps = vec![];
+        for recp in &self.recps {
+            if let DatasetData::String(recp) = recp {
+                if let Ok(relation) = relation::parse(recp) {
+                    recps.push(relation);
+                }
+            }
+        }
+        recps
+    }
+
+    /// Relations in the Recp dataset that are not muted.
+    fn active_recps(&self) -> Vec<Relation> {
+        let mut recps = self.recp_relations();
+        recps.retain(|recp| !self.recp_stats.is_muted(recp));
+        recps
+    }
+
+    /// Recipients for the next send according to the send mode, followed by
+    /// the configured CC peers. When every recipient is muted the mutes are
+    /// honoured: a broadcast fallback goes to the CC peers only.
+    fn recipients(&mut self) -> Vec<Relation> {
+        let mut recps = self.active_recps();
+        if recps.is_empty() {
+            let all_muted = !self.recp_relations().is_empty();
+            return match self.config.no_recp_behavior {
+                NoRecpBehavior::Reject => vec![],
+                NoRecpBehavior::Broadcast if !all_muted => self.with_cc(self.directory.relations()),
+                NoRecpBehavior::Broadcast | NoRecpBehavior::Cc => self.with_cc(recps),
+            };
+        }
+        if self.config.send_mode == SendMode::RoundRobin && !recps.is_empty() {
+            let idx = self.next_recp % recps.len();
+            self.next_recp = idx + 1;
+            recps = vec![recps.swap_remove(idx)];
+        }
+        self.with_cc(recps)
+    }
+
+    /// Append the configured CC peers not already in `recps`.
+    fn with_cc(&self, mut recps: Vec<Relation>) -> Vec<Relation> {
+        for relation in &self.config.cc {
+            if !recps.contains(relation) {
+                recps.push(relation.clone());
+            }
+        }
+        recps
+    }
+
+    async fn schedule(&mut self, client: &mut ClientChannel, input: &str) {
+        match scheduler::parse_request(input, self.clock.now(), self.clock.unix_secs()) {
+            Ok((due, text)) => {
+                let id = self.scheduler.schedule(due, text);
+                self.update_scheduled(client).await;
+                self.status(client, format!("Scheduled #{}", id)).await;
+            }
+            Err(e) => self.status(client, e).await,
+        }
+    }
+
+    async fn cancel_scheduled(&mut self, client: &mut ClientChannel, input: &str) {
+        let note = match input.trim().trim_start_matches('#').parse() {
+            Ok(id) if self.scheduler.cancel(id) => format!("Cancelled #{}", id),
+            Ok(id) => format!("No scheduled send #{}", id),
+            Err(_) => format!("Invalid schedule id: {}", input),
+        };
+        self.update_scheduled(client).await;
+        self.status(client, note).await;
+    }
+
+    async fn update_scheduled(&mut self, client: &mut ClientChannel) {
+        let summary = self.scheduler.summary(self.clock.now());
+        self.set_text("Scheduled", summary);
+        self.push_changes(client).await;
+    }
+
+    /// Resubscribe to the Recp dataset as a probe, since the router always
+    /// answers with its contents. Any message arriving counts as an answer.
+    async fn check_health(&mut self, client: &mut ClientChannel, now: Instant, interval: Duration) {
+        match self.health_probe {
+            Some(sent) if self.last_msg_at.is_some_and(|at| at >= sent) => {
+                self.health_probe = None;
+            }
+            Some(sent) => {
+                if now.duration_since(sent) >= interval {
+                    self.health_probe = None;
+                    self.unhealthy = true;
+                }
+            }
+            None => {
+                if now.duration_since(self.last_probe) >= interval {
+                    let msg = Message::Dataset(DatasetMessage::Subscribe {
+                        path: self.recp_path.clone(),
+                    });
+                    self.traffic.record_sent(&msg);
+                    client.send(msg).await;
+                    self.health_probe = Some(now);
+                    self.last_probe = now;
+                }
+            }
+        }
+    }
+
+    /// Delete messages whose TTL has passed. They are also dropped locally
+    /// so the next sweep does not delete them again before the echo. While
+    /// trim deletes are in flight the known indices are stale, so the sweep
+    /// waits for the echo that shows them applied.
+    async fn sweep_expired(&mut self, client: &mut ClientChannel) {
+        if self.trimmer.in_flight() > 0 {
+            return;
+        }
+        let expired =
+            messages::take_expired(&mut self.msgs, &mut self.msgs_len, self.clock.as_ref());
+        if expired.is_empty() {
+            return;
+        }
+        for &id in &expired {
+            let msg = Message::Dataset(DatasetMessage::DeleteElement {
+                path: self.msgs_path.clone(),
+                id,
+            });
+            self.traffic.record_sent(&msg);
+            client.send(msg).await;
+        }
+        log!("Deleted {} expired messages", expired.len());
+    }
+
+    async fn clear_recps(&mut self, client: &mut ClientChannel) {
+        if self.recps_len == 0 {
+            self.status(client, String::from("No recipients to clear"))
+                .await;
+            return;
+        }
+        for _ in 0..self.recps_len {
+            let msg = Message::Dataset(DatasetMessage::DeleteElement {
+                path: self.recp_path.clone(),
+                id: 0,
+            });
+            self.traffic.record_sent(&msg);
+            client.send(msg).await;
+        }
+        self.recps.clear();
+        self.recps_len = 0;
+        self.update_send_status(client).await;
+        self.status(client, String::from("Recipients cleared"))
+            .await;
+    }
+
+    /// Delete every entry from the Messages dataset. The entries are also
+    /// dropped locally so nothing deletes them again before the echo.
+    async fn clear_msgs(&mut self, client: &mut ClientChannel) {
+        for _ in 0..self.msgs_len {
+            let msg = Message::Dataset(DatasetMessage::DeleteElement {
+                path: self.msgs_path.clone(),
+                id: 0,
+            });
+            self.traffic.record_sent(&msg);
+            client.send(msg).await;
+        }
+        self.msgs.clear();
+        self.msgs_len = 0;
+        self.status(client, String::from("Messages cleared")).await;
+    }
+
+    /// Read a file from disk and send it, encoded, to the recipients.
+    async fn send_file(&mut self, client: &mut ClientChannel, path: &str) {
+        let path = Path::new(path.trim());
+        let bytes = match tokio::fs::read(path).await {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                self.status(client, format!("Failed to read {}: {}", path.display(), e))
+                    .await;
+                return;
+            }
+        };
+        let encoding = self.config.encoding;
+        let encoded = match encoding.encode(&bytes) {
+            Ok(encoded) => encoded,
+            Err(e) => {
+                self.status(client, format!("File not sent: {}", e)).await;
+                return;
+            }
+        };
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let data = messages::file_payload(&name, encoding, encoded);
+        let recps = self.recipients();
+        self.send_event(client, String::from("file"), recps, data)
+            .await;
+    }
+
+    /// Write a received file to the download directory, returning a note
+    /// for the message history.
+    async fn save_file(&self, name: &str, bytes: &[u8]) -> String {
+        // only keep the final path component so senders cannot escape the directory
+        let Some(name) = Path::new(name).file_name() else {
+            return format!("Received file with invalid name {:?}", name);
+        };
+        let dir = &self.config.download_dir;
+        let path = dir.join(name);
+        let result = match tokio::fs::create_dir_all(dir).await {
+            Ok(()) => tokio::fs::write(&path, bytes).await,
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(()) => format!("Received {} ({} bytes)", path.display(), bytes.len()),
+            Err(e) => format!("Failed to save {}: {}", path.display(), e),
+        }
+    }
+
+    /// Ask every recipient to identify itself.
+    async fn send_whoami(&mut self, client: &mut ClientChannel) {
+        let recps = self.recipients();
+        self.send_event(
+            client,
+            String::from("whoami"),
+            recps,
+            DatasetData::String(String::new()),
+        )
+        .await;
+    }
+
+    async fn query_caps(&mut self, client: &mut ClientChannel) {
+        let recps = self.recipients();
+        self.send_event(
+            client,
+            String::from("capabilities"),
+            recps,
+            DatasetData::String(String::new()),
+        )
+        .await;
+    }
+
+    /// Ping every recipient and report who answers within the timeout.
+    async fn test_all(&mut self, client: &mut ClientChannel) {
+        let recps = self.recp_relations();
+        if recps.is_empty() {
+            self.status(client, String::from("No recipients to test"))
+                .await;
+            return;
+        }
+        let batch = PingBatch::new(
+            self.msg_ids.next(),
+            recps.clone(),
+            self.config.ping_timeout,
+            self.clock.now(),
+        );
+        let data = messages::id_payload(batch.id());
+        self.set_text(
+            "Reachability",
+            format!("Pinging {} recipients", recps.len()),
+        );
+        self.ping = Some(batch);
+        self.send_event(client, String::from("ping"), recps, data)
+            .await;
+    }
+
+    /// Show the Test All report once it is complete.
+    async fn finish_ping(&mut self, client: &mut ClientChannel, now: Instant) {
+        match &self.ping {
+            Some(batch) if batch.is_done(now) => {}
+            _ => return,
+        }
+        let Some(batch) = self.ping.take() else {
+            return;
+        };
+        for (recp, rtt) in batch.results() {
+            self.recp_stats.record_ping(recp, rtt.is_some());
+        }
+        let report = batch.render(&self.directory);
+        log!("{}", report);
+        self.set_text("Reachability", report);
+        self.update_send_status(client).await;
+    }
+
+    /// Ping one recipient repeatedly to measure its latency, from input
+    /// `index [count]`.
+    async fn measure_latency(&mut self, client: &mut ClientChannel, input: &str) {
+        let mut parts = input.split_whitespace();
+        let relation = match parts.next().map(str::parse::<usize>) {
+            Some(Ok(idx)) => match self.recps.get(idx) {
+                Some(DatasetData::String(recp)) => relation::parse(recp),
+                _ => Err(format!("no recipient at index {}", idx)),
+            },
+            _ => Err(String::from("usage: <index> [count]")),
+        };
+        let count = match parts.next().map(str::parse::<usize>) {
+            None => Ok(self.config.latency_count),
+            Some(Ok(count)) if count > 0 => Ok(count),
+            Some(_) => Err(String::from("count must be a positive number")),
+        };
+        let (relation, count) = match (relation, count) {
+            (Ok(relation), Ok(count)) => (relation, count),
+            (Err(e), _) | (_, Err(e)) => {
+                self.status(client, e).await;
+                return;
+            }
+        };
+        let ids: Vec<String> = (0..count).map(|_| self.msg_ids.next()).collect();
+        self.latency = Some(LatencyRun::new(
+            relation.clone(),
+            &ids,
+            self.clock.now(),
+            self.config.ping_timeout,
+        ));
+        self.set_text(
+            "Latency Report",
+            format!("Pinging {} {} times", relation::display(&relation), count),
+        );
+        for id in &ids {
+            let data = messages::id_payload(id);
+            self.send_event(client, String::from("ping"), vec![relation.clone()], data)
+                .await;
+        }
+    }
+
+    /// Show the latency summary once the run is complete.
+    async fn finish_latency(&mut self, client: &mut ClientChannel, now: Instant) {
+        match &self.latency {
+            Some(run) if run.is_done(now) => {}
+            _ => return,
+        }
+        let Some(run) = self.latency.take() else {
+            return;
+        };
+        let report = run.render();
+        log!("{}", report);
+        self.set_text("Latency Report", report);
+        self.push_changes(client).await;
+    }
+
+    /// Events received since the last call, for re-emitting on a bridge.
+    fn take_forwarded(&mut self) -> Vec<(String, DatasetData)> {
+        std::mem::take(&mut self.forwarded)
+    }
+
+    /// Re-emit an event bridged from another connection to this
+    /// connection's recipients.
+    async fn forward(&mut self, client: &mut ClientChannel, name: String, data: DatasetData) {
+        let recps = self.recipients();
+        self.send_event(client, name, recps, data).await;
+    }
+
+    async fn append_message(&mut self, client: &mut ClientChannel, entry: DatasetData) {
+        let Some(entry) = self.reconciler.buffer(entry) else {
+            return;
+        };
+        if self.config.batch_window.is_some() {
+            if self.batch.is_empty() {
+                self.batch_started = Some(self.clock.now());
+            }
+            self.batch.push(entry);
+            return;
+        }
+        self.send_append(client, entry).await;
+    }
+
+    /// Append the messages held under `--batch-window` as one entry.
+    async fn flush_batch(&mut self, client: &mut ClientChannel) {
+        self.batch_started = None;
+        let batch = std::mem::take(&mut self.batch);
+        if batch.is_empty() {
+            return;
+        }
+        if batch.len() > 1 {
+            log!("Appending {} batched messages as one entry", batch.len());
+        }
+        self.send_append(client, messages::batch(batch)).await;
+    }
+
+    async fn send_append(&mut self, client: &mut ClientChannel, entry: DatasetData) {
+        self.reconciler.appended(&entry);
+        let msg = Message::Dataset(DatasetMessage::Append {
+            path: self.msgs_path.clone(),
+            data: entry,
+        });
+        self.traffic.record_sent(&msg);
+        client.send(msg).await;
+    }
+
+    /// Toggle buffering of incoming messages, flushing the buffer on resume.
+    async fn toggle_pause(&mut self, client: &mut ClientChannel) {
+        self.paused = !self.paused;
+        if self.paused {
+            self.set_text("Pause", String::from("Resume"));
+            self.status(client, String::from("Message view paused"))
+                .await;
+        } else {
+            self.set_text("Pause", String::from("Pause"));
+            let buffered = std::mem::take(&mut self.paused_msgs);
+            let count = buffered.len();
+            for entry in buffered {
+                self.append_message(client, entry).await;
+            }
+            self.status(
+                client,
+                format!("Resumed, {} buffered messages added", count),
+            )
+            .await;
+        }
+    }
+
+    fn is_allowed(&self, sender: &Relation) -> bool {
+        self.config.allowlist.is_empty() || self.config.allowlist.contains(sender)
+    }
+
+    async fn router_handler(&mut self, client: &mut ClientChannel, msg: RouterMessage) {
+        match msg {
+            // Authorization Messages
+            RouterMessage::Pending => {}
+            RouterMessage::ApprovalCode(code) => {
+                let path = &self.config.approval_code_path;
+                match tokio::fs::write(path, code.as_bytes()).await {
+                    Ok(()) => log!(Auth; "Approval code written to {}", path.display()),
+                    Err(e) => {
+                        log!(Error; "Failed to write approval code to {}: {}", path.display(), e)
+                    }
+                }
+            }
+            RouterMessage::Approved => {
+                let path = &self.config.approval_code_path;
+                if let Err(e) = tokio::fs::remove_file(path).await {
+                    if e.kind() != io::ErrorKind::NotFound {
+                        log!(Error; "Failed to remove {}: {}", path.display(), e);
+                    }
+                }
+                log!(Auth; "Router approved");
+            }
+            RouterMessage::Denied => {
+                log!(Auth; "Router denied this client");
+                self.denied = true;
+                self.denied_at = Some(self.clock.now());
+            }
+
+            // Routing Messages
+            RouterMessage::SendEvent(name, recps, data) => {
+                if self.config.relay {
+                    log!("Relaying {:?} to {} recipients", name, recps.len());
+                    self.send_event(client, name, recps, data).await;
+                }
+            }
+            RouterMessage::Event(name, from, data) => {
+                self.last_activity = self.clock.now();
+                self.last_event_at = Some(self.last_activity);
+                if !self.is_allowed(&from) {
+                    log!(Event; "Dropped event {:?} from unlisted sender: {:?}", name, from);
+                    return;
+                }
+                output::event(&name, &from, &data);
+                if self.forwarding {
+                    self.forwarded.push((name.clone(), data.clone()));
+                }
+                match name.as_str() {
+                    "test_event" => {
+                        if self.config.auto_add_senders {
+                            self.auto_add_sender(client, &from).await;
+                        }
+                        if let Some(id) = messages::id(&data) {
+                            let msg = Message::Router(RouterMessage::SendEvent(
+                                String::from("receipt"),
+                                vec![from.clone()],
+                                messages::id_payload(id),
+                            ));
+                            self.traffic.record_sent(&msg);
+                            client.send(msg).await;
+                            let key = format!("{}/{}", relation::display(&from), id);
+                            if !self.dedup.insert(key) {
+                                log!(Event; "Dropped duplicate message {}", id);
+                                return;
+                            }
+                        }
+                        let verified = if self.config.sign {
+                            let key = self.directory.property(&from, sign::KEY_PROPERTY);
+                            let verified = sign::verify(&data, key);
+                            if let Err(reason) = verified {
+                                log!(Auth; "Unverified message from {}: {}",
+                                    relation::display(&from), reason);
+                            }
+                            Some(verified)
+                        } else {
+                            None
+                        };
+                        let mismatch = match &self.config.schema {
+                            Some(schema) => schema.validate(&data).err(),
+                            None => None,
+                        };
+                        if let Some(reason) = &mismatch {
+                            log!(Error; "Payload from {} does not match the schema: {}",
+                                relation::display(&from), reason);
+                            if self.config.schema_mode == SchemaMode::Reject {
+                                return;
+                            }
+                        }
+                        let data = match &self.config.text_field {
+                            Some(field) => messages::map_text_field(data, field),
+                            None => data,
+                        };
+                        let data = self.config.transform_in.apply_data(data);
+                        let (data, malformed) = messages::normalize(data);
+                        let mut entry = messages::entry(
+                            data,
+                            &from,
+                            &self.config.time_format,
+                            self.clock.unix_secs(),
+                        );
+                        if let Some(note) = malformed {
+                            log!(Error; "Malformed payload from {}: {}",
+                                relation::display(&from), note);
+                            entry = messages::with_malformed(entry, note);
+                        }
+                        if let Some(reason) = mismatch {
+                            entry = messages::with_schema_error(entry, reason);
+                        }
+                        if let Some(verified) = verified {
+                            entry = messages::with_verification(entry, verified);
+                        }
+                        // replies to replies would bounce between two responders forever
+                        let reply = match &self.config.auto_respond {
+                            Some(template) if !messages::is_auto_reply(&entry) => Some(
+                                template
+                                    .replace("{text}", &messages::text(&entry))
+                                    .replace("{sender}", &relation::display(&from)),
+                            ),
+                            _ => None,
+                        };
+                        let ready = match &mut self.sequencer {
+                            Some(sequencer) => {
+                                let seq = messages::seq(&entry);
+                                let session = messages::session(&entry).unwrap_or_default();
+                                let key = (from.clone(), session.to_string());
+                                sequencer.accept(&key, seq, entry, self.clock.now())
+                            }
+                            None => vec![entry],
+                        };
+                        self.store_messages(client, ready).await;
+                        if let Some(text) = reply {
+                            let data = messages::auto_reply(text, self.msg_ids.next());
+                            self.send_event(client, String::from("test_event"), vec![from], data)
+                                .await;
+                        }
+                    }
+                    "receipt" => {
+                        if let Some(id) = messages::id(&data) {
+                            if self.sends.record_receipt(id, &from) {
+                                self.update_send_status(client).await;
+                            }
+                        }
+                    }
+                    "reconnected" => {
+                        let text = format!(
+                            "{} ({}) reconnected",
+                            messages::text(&data),
+                            relation::display(&from)
+                        );
+                        self.status(client, text).await;
+                    }
+                    "ping" => {
+                        let msg = Message::Router(RouterMessage::SendEvent(
+                            String::from("pong"),
+                            vec![from],
+                            data,
+                        ));
+                        self.traffic.record_sent(&msg);
+                        client.send(msg).await;
+                    }
+                    "pong" => {
+                        let now = self.clock.now();
+                        let answered = match (&mut self.ping, messages::id(&data)) {
+                            (Some(batch), Some(id)) => batch.record(id, &from, now),
+                            _ => false,
+                        };
+                        if answered {
+                            self.finish_ping(client, now).await;
+                        }
+                        let timed = match (&mut self.latency, messages::id(&data)) {
+                            (Some(run), Some(id)) => run.record(id, &from, now),
+                            _ => false,
+                        };
+                        if timed {
+                            self.finish_latency(client, now).await;
+                        }
+                    }
+                    "whoami" => {
+                        let reply = messages::whoami_reply(&self.config.name, &client.id());
+                        let msg = Message::Router(RouterMessage::SendEvent(
+                            String::from("whoami_reply"),
+                            vec![from],
+                            reply,
+                        ));
+                        self.traffic.record_sent(&msg);
+                        client.send(msg).await;
+                    }
+                    "capabilities" => {
+                        let events = self.subscriptions.events.clone();
+                        let msg = Message::Router(RouterMessage::SendEvent(
+                            String::from("capabilities_reply"),
+                            vec![from],
+                            messages::capabilities_reply(&events),
+                        ));
+                        self.traffic.record_sent(&msg);
+                        client.send(msg).await;
+                    }
+                    "capabilities_reply" => {
+                        let text = messages::describe_capabilities(&data);
+                        let entry = messages::entry(
+                            DatasetData::String(text),
+                            &from,
+                            &self.config.time_format,
+                            self.clock.unix_secs(),
+                        );
+                        self.append_message(client, entry).await;
+                    }
+                    "whoami_reply" => {
+                        let text = messages::describe_whoami(&data);
+                        let entry = messages::entry(
+                            DatasetData::String(text),
+                            &from,
+                            &self.config.time_format,
+                            self.clock.unix_secs(),
+                        );
+                        self.append_message(client, entry).await;
+                    }
+                    "file" => {
+                        let text = match messages::decode_file(&data) {
+                            Ok((name, bytes)) => self.save_file(&name, &bytes).await,
+                            Err(e) => format!("Unreadable file: {}", e),
+                        };
+                        let entry = messages::entry(
+                            DatasetData::String(text),
+                            &from,
+                            &self.config.time_format,
+                            self.clock.unix_secs(),
+                        );
+                        self.append_message(client, entry).await;
+                    }
+                    _ if self.subscriptions.matches_pattern(&name) => {
+                        let msg = Message::Dataset(DatasetMessage::Append {
+                            path: event_dataset(&name),
+                            data: messages::entry(
+                                data,
+                                &from,
+                                &self.config.time_format,
+                                self.clock.unix_secs(),
+                            ),
+                        });
+                        self.traffic.record_sent(&msg);
+                        client.send(msg).await;
+                    }
+                    _ if self.config.log_unknown_events => {
+                        let count = self.unknown_events.entry(name.clone()).or_default();
+                        *count += 1;
+                        log!(Event; "Unhandled event {:?} (seen {} times)", name, count);
+                        let msg = Message::Dataset(DatasetMessage::Append {
+                            path: unhandled_dataset(),
+                            data: messages::unhandled_entry(
+                                name,
+                                data,
+                                &from,
+                                &self.config.time_format,
+                                self.clock.unix_secs(),
+                            ),
+                        });
+                        self.traffic.record_sent(&msg);
+                        client.send(msg).await;
+                    }
+                    _ => {}
+                }
+            }
+            RouterMessage::Subscribe(_) => {}
+            RouterMessage::Unsubscribe(_) => {}
+
+            // directory messages
+            RouterMessage::SubscribeDir => {}
+            RouterMessage::UnsubscribeDir => {
+                // no further updates will arrive, so the copy would only go stale
+                log!("Unsubscribed from the directory");
+                self.directory.clear();
+                self.update_directory(client).await;
+            }
+            RouterMessage::AddIdentity(entry) => {
+                self.directory.add(entry);
+                self.directory_changed(client).await;
+            }
+            RouterMessage::RemoveIdentity(relation) => {
+                self.directory.remove(&relation);
+                self.directory_changed(client).await;
+            }
+            RouterMessage::SetIdentityProperty(key, value) => {
+                // the router only echoes this client's own properties
+                log!("Router confirmed property {} = {}", key, value);
+                if let Ok(own) = relation::parse(&client.id().to_base64()) {
+                    if self.directory.set_property(&own, key, value) {
+                        self.directory_changed(client).await;
+                    }
+                }
+            }
 
             // Chord Messages
             RouterMessage::SubscribeChord(_) => {}
@@ -0,0 +1,30 @@
+use spider_client::Relation;
+
+use crate::relation;
+
+/// Split leading `@<base64>` mentions off a message. Returns `None` when
+/// the text does not start with a mention, otherwise the mentioned
+/// relations and the remaining text.
+pub fn parse(text: &str) -> Option<Result<(Vec<Relation>, String), String>> {
+    if !text.starts_with('@') {
+        return None;
+    }
+    let mut recps = vec![];
+    let mut rest = text;
+    while let Some(mention) = rest.strip_prefix('@') {
+        let (token, tail) = mention.split_once(' ').unwrap_or((mention, ""));
+        match relation::parse(token) {
+            Ok(relation) => {
+                if !recps.contains(&relation) {
+                    recps.push(relation);
+                }
+            }
+            Err(e) => return Some(Err(format!("bad mention @{}: {}", token, e))),
+        }
+        rest = tail.trim_start();
+    }
+    if rest.is_empty() {
+        return Some(Err(String::from("usage: @<base64> [@<base64>..] <text>")));
+    }
+    Some(Ok((recps, rest.to_string())))
+}
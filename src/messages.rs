@@ -0,0 +1,547 @@
+use std::{
+    collections::BTreeMap,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use spider_client::{message::DatasetData, Relation, SpiderId2048};
+
+use crate::{clock::Clock, encoding::Encoding, relation, timefmt::TimeFormat};
+
+/// Build the Messages dataset entry for a received event.
+///
+/// Entries are maps so the display template can refer to individual fields:
+/// `text` holds the payload, `sender` the sender's base-64 relation,
+/// `timestamp` the receive time in the configured format and `received_at`
+/// the receive time in seconds since the epoch, as read from the state's
+/// clock. A map payload that already has a `text` field, such as one from
+/// [`outbound`], keeps its other fields too.
+pub fn entry(
+    data: DatasetData,
+    sender: &Relation,
+    time_format: &TimeFormat,
+    received_at: u64,
+) -> DatasetData {
+    let mut map = match data {
+        DatasetData::Map(map) if map.contains_key("text") => map,
+        data => {
+            let mut map = BTreeMap::new();
+            map.insert(String::from("text"), data);
+            map
+        }
+    };
+    map.insert(
+        String::from("sender"),
+        DatasetData::String(relation::to_base64(sender)),
+    );
+    map.insert(
+        String::from("timestamp"),
+        DatasetData::String(time_format.format(received_at)),
+    );
+    map.insert(
+        String::from("received_at"),
+        DatasetData::String(received_at.to_string()),
+    );
+    DatasetData::Map(map)
+}
+
+/// Give a map payload that has no `text` field one, copied from `field`
+/// or, if that is missing too, the map's debug form. Other payloads are
+/// returned unchanged.
+pub fn map_text_field(data: DatasetData, field: &str) -> DatasetData {
+    match data {
+        DatasetData::Map(mut map) if !map.contains_key("text") => {
+            let text = match map.get(field) {
+                Some(DatasetData::String(text)) => text.clone(),
+                Some(data) => format!("{:?}", data),
+                None => format!("{:?}", map),
+            };
+            map.insert(String::from("text"), DatasetData::String(text));
+            DatasetData::Map(map)
+        }
+        data => data,
+    }
+}
+
+/// Make sure a received payload has a string `text` for the message view.
+/// Payloads of other shapes are coerced to their debug form and the
+/// returned note says what was wrong; well-formed payloads pass unchanged.
+pub fn normalize(data: DatasetData) -> (DatasetData, Option<&'static str>) {
+    match data {
+        DatasetData::String(_) => (data, None),
+        DatasetData::Map(mut map) => match map.get("text") {
+            Some(DatasetData::String(_)) => (DatasetData::Map(map), None),
+            Some(text) => {
+                let text = format!("{:?}", text);
+                map.insert(String::from("text"), DatasetData::String(text));
+                (DatasetData::Map(map), Some("text is not a string"))
+            }
+            None => {
+                let text = format!("{:?}", map);
+                (DatasetData::String(text), Some("map has no text"))
+            }
+        },
+        data => (
+            DatasetData::String(format!("{:?}", data)),
+            Some("payload is not text or a map"),
+        ),
+    }
+}
+
+/// Label a Messages entry built from a payload [`normalize`] had to coerce.
+pub fn with_malformed(data: DatasetData, note: &str) -> DatasetData {
+    match data {
+        DatasetData::Map(mut map) => {
+            map.insert(
+                String::from("malformed"),
+                DatasetData::String(note.to_string()),
+            );
+            DatasetData::Map(map)
+        }
+        data => data,
+    }
+}
+
+/// Payload for an outgoing `test_event`: the text and an id unique to this
+/// sender, so receivers can drop repeated deliveries.
+pub fn outbound(text: String, id: String) -> DatasetData {
+    let mut map = BTreeMap::new();
+    map.insert(String::from("text"), DatasetData::String(text));
+    map.insert(String::from("id"), DatasetData::String(id));
+    DatasetData::Map(map)
+}
+
+/// Payload for an `--auto-respond` reply, marked so that receivers which
+/// also auto-respond do not answer it.
+pub fn auto_reply(text: String, id: String) -> DatasetData {
+    match outbound(text, id) {
+        DatasetData::Map(mut map) => {
+            map.insert(
+                String::from("auto_reply"),
+                DatasetData::String(String::from("true")),
+            );
+            DatasetData::Map(map)
+        }
+        data => data,
+    }
+}
+
+/// Whether a payload or entry is an `--auto-respond` reply.
+pub fn is_auto_reply(data: &DatasetData) -> bool {
+    match data {
+        DatasetData::Map(map) => map.contains_key("auto_reply"),
+        _ => false,
+    }
+}
+
+/// Number an outgoing payload in this sender's sequence, for receivers
+/// running with `--in-order`.
+pub fn with_seq(data: DatasetData, seq: u64) -> DatasetData {
+    match data {
+        DatasetData::Map(mut map) => {
+            map.insert(String::from("seq"), DatasetData::String(seq.to_string()));
+            DatasetData::Map(map)
+        }
+        data => data,
+    }
+}
+
+/// The sequence number of a payload or entry, if it has one.
+pub fn seq(data: &DatasetData) -> Option<u64> {
+    field(data, "seq")?.parse().ok()
+}
+
+/// The session prefix of a payload's id, which [`MessageIds`] makes
+/// distinct for every run of the sender.
+pub fn session(data: &DatasetData) -> Option<&str> {
+    id(data)?.split_once('-').map(|(session, _)| session)
+}
+
+/// Add a time-to-live, in whole seconds, to an outgoing payload.
+pub fn with_ttl(data: DatasetData, ttl: Duration) -> DatasetData {
+    match data {
+        DatasetData::Map(mut map) => {
+            map.insert(
+                String::from("ttl"),
+                DatasetData::String(ttl.as_secs().to_string()),
+            );
+            DatasetData::Map(map)
+        }
+        data => data,
+    }
+}
+
+/// Mark a Messages entry whose payload did not match the configured schema.
+pub fn with_schema_error(data: DatasetData, reason: String) -> DatasetData {
+    match data {
+        DatasetData::Map(mut map) => {
+            map.insert(String::from("schema_error"), DatasetData::String(reason));
+            DatasetData::Map(map)
+        }
+        data => data,
+    }
+}
+
+/// Record on a Messages entry whether its signature checked out, as
+/// `verified` or the reason it did not.
+pub fn with_verification(data: DatasetData, verified: Result<(), &str>) -> DatasetData {
+    match data {
+        DatasetData::Map(mut map) => {
+            let status = match verified {
+                Ok(()) => "verified",
+                Err(reason) => reason,
+            };
+            map.insert(
+                String::from("signature_check"),
+                DatasetData::String(status.to_string()),
+            );
+            DatasetData::Map(map)
+        }
+        data => data,
+    }
+}
+
+/// When a Messages entry with a `ttl` expires, in seconds since the epoch.
+pub fn expires_at(entry: &DatasetData) -> Option<u64> {
+    let DatasetData::Map(map) = entry else {
+        return None;
+    };
+    let field = |key: &str| match map.get(key) {
+        Some(DatasetData::String(value)) => value.parse::<u64>().ok(),
+        _ => None,
+    };
+    Some(field("received_at")? + field("ttl")?)
+}
+
+/// Indices of the entries whose TTL has passed at `now`, in seconds since
+/// the epoch.
+pub fn expired(entries: &[DatasetData], now: u64) -> Vec<usize> {
+    (0..entries.len())
+        .filter(|&idx| expires_at(&entries[idx]).is_some_and(|at| at <= now))
+        .collect()
+}
+
+/// Remove the entries of `tail` whose TTL has passed by `clock`'s time.
+///
+/// `tail` holds the last entries of a dataset `len` entries long. Returns
+/// the dataset indices of the removed entries, newest first so deleting
+/// them in order keeps the rest valid, and shortens `len` to match.
+pub fn take_expired(tail: &mut Vec<DatasetData>, len: &mut usize, clock: &dyn Clock) -> Vec<usize> {
+    let offset = *len - tail.len();
+    let mut expired = expired(tail, clock.unix_secs());
+    expired.reverse();
+    for &idx in &expired {
+        tail.remove(idx);
+    }
+    *len -= expired.len();
+    expired.iter().map(|idx| offset + idx).collect()
+}
+
+/// Coalesce Messages entries received in one `--batch-window` into a
+/// single entry, so the burst costs one append.
+///
+/// `text` holds each entry's text on its own line and `count` how many
+/// there were. `id` joins the entries' ids so the batch can still be
+/// reconciled after a reconnect. `sender` is the one sender, or the
+/// distinct senders comma separated, and the times are the newest entry's.
+/// The batch expires with its last entry to expire, and only if every
+/// entry has a TTL.
+pub fn batch(mut entries: Vec<DatasetData>) -> DatasetData {
+    if entries.len() == 1 {
+        return entries.remove(0);
+    }
+    let texts: Vec<String> = entries.iter().map(text).collect();
+    let ids: Vec<&str> = entries.iter().filter_map(id).collect();
+    let mut senders: Vec<&str> = vec![];
+    for sender in entries.iter().filter_map(|entry| field(entry, "sender")) {
+        if !senders.contains(&sender) {
+            senders.push(sender);
+        }
+    }
+    let expiry = entries
+        .iter()
+        .map(expires_at)
+        .collect::<Option<Vec<u64>>>()
+        .and_then(|at| at.into_iter().max());
+
+    let mut map = BTreeMap::new();
+    let mut set = |key: &str, value: String| {
+        map.insert(key.to_string(), DatasetData::String(value));
+    };
+    set("text", texts.join("\n"));
+    set("count", entries.len().to_string());
+    if !ids.is_empty() {
+        set("id", ids.join(","));
+    }
+    if !senders.is_empty() {
+        set("sender", senders.join(","));
+    }
+    let newest = entries.last();
+    for key in ["timestamp", "received_at"] {
+        if let Some(value) = newest.and_then(|entry| field(entry, key)) {
+            set(key, value.to_string());
+        }
+    }
+    let received_at = newest.and_then(|entry| field(entry, "received_at")?.parse::<u64>().ok());
+    if let (Some(expiry), Some(received_at)) = (expiry, received_at) {
+        set("ttl", expiry.saturating_sub(received_at).to_string());
+    }
+    DatasetData::Map(map)
+}
+
+/// The sender-supplied id of a payload, if it has one.
+pub fn id(data: &DatasetData) -> Option<&str> {
+    field(data, "id")
+}
+
+/// A string field of a map payload or entry.
+pub fn field<'a>(data: &'a DatasetData, key: &str) -> Option<&'a str> {
+    match data {
+        DatasetData::Map(map) => match map.get(key) {
+            Some(DatasetData::String(value)) => Some(value),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Payload carrying only an id, for `receipt` events acknowledging a
+/// message and for `ping` and `pong`.
+pub fn id_payload(id: &str) -> DatasetData {
+    let mut map = BTreeMap::new();
+    map.insert(String::from("id"), DatasetData::String(id.to_string()));
+    DatasetData::Map(map)
+}
+
+/// Hands out ids for outgoing messages. The session start time keeps ids
+/// from one run distinct from the next.
+pub struct MessageIds {
+    session: u128,
+    next: u64,
+}
+
+impl Default for MessageIds {
+    fn default() -> Self {
+        let session = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or_default();
+        Self { session, next: 0 }
+    }
+}
+
+impl MessageIds {
+    pub fn next(&mut self) -> String {
+        let id = format!("{}-{}", self.session, self.next);
+        self.next += 1;
+        id
+    }
+}
+
+/// Build an Unhandled dataset entry: a message entry with the event name
+/// under `event`.
+pub fn unhandled_entry(
+    name: String,
+    data: DatasetData,
+    sender: &Relation,
+    time_format: &TimeFormat,
+    received_at: u64,
+) -> DatasetData {
+    let mut entry = entry(data, sender, time_format, received_at);
+    if let DatasetData::Map(map) = &mut entry {
+        map.insert(String::from("event"), DatasetData::String(name));
+    }
+    entry
+}
+
+/// The display text of a Messages entry.
+pub fn text(entry: &DatasetData) -> String {
+    let data = match entry {
+        DatasetData::Map(map) => match map.get("text") {
+            Some(data) => data,
+            None => return format!("{:?}", entry),
+        },
+        data => data,
+    };
+    match data {
+        DatasetData::String(text) => text.clone(),
+        data => format!("{:?}", data),
+    }
+}
+
+/// Payload answering a `whoami` event: this router's name and relation.
+pub fn whoami_reply(name: &str, id: &SpiderId2048) -> DatasetData {
+    let mut map = BTreeMap::new();
+    map.insert(String::from("name"), DatasetData::String(name.to_string()));
+    map.insert(
+        String::from("relation"),
+        DatasetData::String(format!("peer:{}", id.to_base64())),
+    );
+    DatasetData::Map(map)
+}
+
+/// Readable summary of a `whoami_reply` payload.
+pub fn describe_whoami(data: &DatasetData) -> String {
+    let field = |key: &str| match data {
+        DatasetData::Map(map) => match map.get(key) {
+            Some(DatasetData::String(value)) => value.clone(),
+            _ => String::from("?"),
+        },
+        _ => String::from("?"),
+    };
+    format!("whoami: {} ({})", field("name"), field("relation"))
+}
+
+/// Payload answering a `capabilities` event: the handled event names,
+/// comma separated.
+pub fn capabilities_reply(events: &[String]) -> DatasetData {
+    let mut map = BTreeMap::new();
+    map.insert(
+        String::from("events"),
+        DatasetData::String(events.join(",")),
+    );
+    DatasetData::Map(map)
+}
+
+/// Readable summary of a `capabilities_reply` payload.
+pub fn describe_capabilities(data: &DatasetData) -> String {
+    let events = match data {
+        DatasetData::Map(map) => match map.get("events") {
+            Some(DatasetData::String(events)) => events.replace(',', ", "),
+            _ => String::from("?"),
+        },
+        _ => String::from("?"),
+    };
+    format!("capabilities: {}", events)
+}
+
+/// Payload for a `file` event: the file name, the encoding tag and the
+/// encoded contents.
+pub fn file_payload(name: &str, encoding: Encoding, encoded: String) -> DatasetData {
+    let mut map = BTreeMap::new();
+    map.insert(String::from("name"), DatasetData::String(name.to_string()));
+    map.insert(
+        String::from("encoding"),
+        DatasetData::String(encoding.tag().to_string()),
+    );
+    map.insert(String::from("data"), DatasetData::String(encoded));
+    DatasetData::Map(map)
+}
+
+/// Decode a `file` event payload into its name and contents.
+pub fn decode_file(data: &DatasetData) -> Result<(String, Vec<u8>), String> {
+    let DatasetData::Map(map) = data else {
+        return Err(String::from("file payload is not a map"));
+    };
+    let field = |key: &str| match map.get(key) {
+        Some(DatasetData::String(value)) => Ok(value.as_str()),
+        _ => Err(format!("file payload is missing {:?}", key)),
+    };
+    let tag = field("encoding")?;
+    let encoding = Encoding::from_tag(tag).ok_or_else(|| format!("unknown encoding {:?}", tag))?;
+    let bytes = encoding.decode(field("data")?)?;
+    Ok((field("name")?.to_string(), bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{Clock, ManualClock};
+
+    /// A Messages entry received now by `clock`, expiring after `ttl`.
+    fn received(clock: &ManualClock, text: &str, ttl: u64) -> DatasetData {
+        let mut map = BTreeMap::new();
+        map.insert(String::from("text"), DatasetData::String(text.to_string()));
+        map.insert(
+            String::from("received_at"),
+            DatasetData::String(clock.unix_secs().to_string()),
+        );
+        with_ttl(DatasetData::Map(map), Duration::from_secs(ttl))
+    }
+
+    #[test]
+    fn entries_expire_as_the_clock_advances() {
+        let clock = ManualClock::new(1_000);
+        let mut entries = vec![received(&clock, "short", 5)];
+        clock.advance(Duration::from_secs(2));
+        entries.push(received(&clock, "long", 30));
+        entries.push(DatasetData::String(String::from("no ttl")));
+
+        assert!(expired(&entries, clock.unix_secs()).is_empty());
+        clock.advance(Duration::from_secs(3));
+        assert_eq!(expired(&entries, clock.unix_secs()), vec![0]);
+        clock.advance(Duration::from_secs(26));
+        assert_eq!(expired(&entries, clock.unix_secs()), vec![0]);
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(expired(&entries, clock.unix_secs()), vec![0, 1]);
+    }
+
+    /// The sweep `State` runs each tick: expired entries leave the tail and
+    /// the dataset length, and their deletes name dataset indices.
+    #[test]
+    fn sweep_removes_expired_entries_from_the_tail() {
+        let clock = ManualClock::new(1_000);
+        let mut tail = vec![
+            received(&clock, "a", 10),
+            DatasetData::String(String::from("kept")),
+            received(&clock, "b", 5),
+            received(&clock, "c", 10),
+        ];
+        // the tail is the last four of six entries
+        let mut len = 6;
+
+        assert!(take_expired(&mut tail, &mut len, &clock).is_empty());
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(take_expired(&mut tail, &mut len, &clock), vec![4]);
+        assert_eq!((tail.len(), len), (3, 5));
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(take_expired(&mut tail, &mut len, &clock), vec![4, 2]);
+        assert_eq!((tail.len(), len), (1, 3));
+        assert_eq!(text(&tail[0]), "kept");
+    }
+
+    #[test]
+    fn timestamps_come_from_the_clock() {
+        let clock = ManualClock::new(1_000);
+        clock.advance(Duration::from_secs(234));
+        let at = clock.unix_secs();
+        assert_eq!(TimeFormat::EpochSecs.format(at), "1234");
+        assert_eq!(TimeFormat::EpochMillis.format(at), "1234000");
+        assert_eq!(TimeFormat::Rfc3339.format(at), "1970-01-01T00:20:34Z");
+    }
+
+    #[test]
+    fn batch_coalesces_a_burst_into_one_entry() {
+        let clock = ManualClock::new(1_000);
+        let first = received(&clock, "one", 10);
+        clock.advance(Duration::from_secs(2));
+        let second = received(&clock, "two", 3);
+        let mut ids = MessageIds::default();
+        let with_id = |entry: DatasetData, id: String| match entry {
+            DatasetData::Map(mut map) => {
+                map.insert(String::from("id"), DatasetData::String(id));
+                DatasetData::Map(map)
+            }
+            entry => entry,
+        };
+        let (a, b) = (ids.next(), ids.next());
+        let entry = batch(vec![with_id(first, a.clone()), with_id(second, b.clone())]);
+
+        assert_eq!(text(&entry), "one\ntwo");
+        assert_eq!(field(&entry, "count"), Some("2"));
+        assert_eq!(id(&entry), Some(format!("{},{}", a, b).as_str()));
+        assert_eq!(field(&entry, "received_at"), Some("1002"));
+        assert_eq!(expires_at(&entry), Some(1_010));
+    }
+
+    #[test]
+    fn batch_without_every_ttl_does_not_expire() {
+        let clock = ManualClock::new(1_000);
+        let entries = vec![
+            received(&clock, "one", 10),
+            DatasetData::String(String::from("two")),
+        ];
+        let entry = batch(entries);
+        assert_eq!(text(&entry), "one\ntwo");
+        assert_eq!(expires_at(&entry), None);
+    }
+}
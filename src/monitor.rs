@@ -0,0 +1,87 @@
+use spider_client::{
+    message::{Message, RouterMessage},
+    ClientChannel, ClientResponse,
+};
+
+use crate::{
+    config::{Config, Mode},
+    messages, output, relation,
+    sanitize::{self, SanitizePolicy},
+    subscriptions::Subscriptions,
+    traffic::Traffic,
+};
+
+/// Print every event received until the connection closes.
+///
+/// No page is built and no datasets are subscribed; only the built-in events
+/// and any `--subscribe-pattern` patterns are requested from the router. In
+/// tail mode only test messages are printed, one line each, or one JSON
+/// object each under `--output json`.
+pub async fn run(client: &mut ClientChannel, config: &Config, traffic: &mut Traffic) {
+    let tail = config.mode == Mode::Tail;
+    let subscriptions = Subscriptions {
+        datasets: vec![],
+        patterns: config.event_patterns.clone(),
+        directory: false,
+        ..Default::default()
+    };
+    subscriptions.send_all(client, traffic).await;
+
+    loop {
+        let msg = match client.recv().await {
+            Some(ClientResponse::Message(msg)) => {
+                traffic.record_received(&msg);
+                msg
+            }
+            Some(ClientResponse::Denied(_)) => {
+                log!(Auth; "Router denied this client");
+                return;
+            }
+            None => return,
+            _ => continue,
+        };
+        match msg {
+            Message::Router(RouterMessage::Event(name, from, data)) => {
+                if !config.allowlist.is_empty() && !config.allowlist.contains(&from) {
+                    continue;
+                }
+                if tail {
+                    if name != "test_event" {
+                        continue;
+                    }
+                    if output::is_json() {
+                        output::event(&name, &from, &data);
+                    } else {
+                        // Strip never refuses, it only removes what is unsafe
+                        let text =
+                            sanitize::sanitize(&messages::text(&data), SanitizePolicy::Strip)
+                                .unwrap_or_default()
+                                .replace('\n', " ");
+                        println!(
+                            "{} {}: {}",
+                            config.time_format.now(),
+                            relation::display(&from),
+                            text
+                        );
+                    }
+                } else if output::is_json() {
+                    output::event(&name, &from, &data);
+                } else {
+                    log!(Event; "{} from {}: {:?}", name, relation::display(&from), data);
+                }
+            }
+            Message::Router(RouterMessage::ApprovalCode(code)) => {
+                let path = &config.approval_code_path;
+                match tokio::fs::write(path, code.as_bytes()).await {
+                    Ok(()) => log!(Auth; "Approval code written to {}", path.display()),
+                    Err(e) => {
+                        log!(Error; "Failed to write approval code to {}: {}", path.display(), e)
+                    }
+                }
+            }
+            Message::Router(RouterMessage::Approved) => log!(Auth; "Router approved"),
+            Message::Error(e) => log!(Error; "Error: {:?}", e),
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,102 @@
+use spider_client::{
+    message::{Message, RouterMessage},
+    ClientChannel, ClientResponse,
+};
+
+use crate::{
+    config::Config,
+    messages::{self, MessageIds},
+    relation,
+    traffic::Traffic,
+};
+
+/// Send a single message to the recipient file and CC peers, then return
+/// whether it was delivered.
+///
+/// The message carries an id like any interactive send. The ack window is
+/// always waited out so the send is handed to the router before the
+/// process exits, and an error from the router in that time fails it. With
+/// `--once-wait` every recipient must also answer with a receipt for the
+/// id before the window ends.
+pub async fn run(
+    client: &mut ClientChannel,
+    config: &Config,
+    text: String,
+    traffic: &mut Traffic,
+) -> bool {
+    let mut recps = config.file_recps.clone();
+    for relation in &config.cc {
+        if !recps.contains(relation) {
+            recps.push(relation.clone());
+        }
+    }
+    if recps.is_empty() {
+        log!("send needs recipients from --recp-file or --cc");
+        return false;
+    }
+
+    if config.once_wait {
+        let msg = Message::Router(RouterMessage::Subscribe(String::from("receipt")));
+        traffic.record_sent(&msg);
+        client.send(msg).await;
+    }
+    let id = MessageIds::default().next();
+    let msg = Message::Router(RouterMessage::SendEvent(
+        String::from("test_event"),
+        recps.clone(),
+        messages::outbound(text, id.clone()),
+    ));
+    traffic.record_sent(&msg);
+    client.send(msg).await;
+
+    let mut unacked = recps;
+    let deadline = tokio::time::sleep(config.ack_window);
+    tokio::pin!(deadline);
+    loop {
+        if config.once_wait && unacked.is_empty() {
+            log!("Delivered");
+            return true;
+        }
+        tokio::select! {
+            _ = &mut deadline => {
+                if !config.once_wait {
+                    log!("Sent");
+                    return true;
+                }
+                for recp in &unacked {
+                    log!(Error; "No receipt from {}", relation::display(recp));
+                }
+                return false;
+            }
+            response = client.recv() => {
+                if let Some(ClientResponse::Message(msg)) = &response {
+                    traffic.record_received(msg);
+                }
+                match response {
+                    Some(ClientResponse::Message(Message::Router(RouterMessage::Event(
+                        name,
+                        from,
+                        data,
+                    )))) if name == "receipt" && messages::id(&data) == Some(id.as_str()) => {
+                        unacked.retain(|recp| *recp != from);
+                    }
+                    Some(ClientResponse::Message(Message::Router(
+                        RouterMessage::ApprovalCode(_),
+                    ))) => {
+                        log!(Error; "Router has not approved this client yet");
+                        return false;
+                    }
+                    Some(ClientResponse::Message(Message::Error(e))) => {
+                        log!(Error; "Send failed: {:?}", e);
+                        return false;
+                    }
+                    Some(ClientResponse::Denied(_)) | None => {
+                        log!(Error; "Connection closed before the send was confirmed");
+                        return false;
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,230 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    hash::Hash,
+    time::{Duration, Instant},
+};
+
+use spider_client::{message::DatasetData, Relation};
+
+/// Puts each sender's messages back in `seq` order before they are stored.
+///
+/// Queues are keyed by `K`, which in the router is the sender together
+/// with the session prefix of its message ids, so a sender that restarts
+/// and counts from 0 again gets a fresh queue. A message ahead of the next expected number is held until the gap fills
+/// or the oldest held message has waited for the timeout, at which point
+/// the gap is given up on. Messages without a `seq` pass straight through,
+/// as do late messages behind the next expected number. A `seq` more than
+/// [`RESTART_GAP`] behind is taken as the sender starting its count again.
+/// A count that runs past `u64::MAX` starts over from the next message.
+pub struct Sequencer<K = (Relation, String)> {
+    timeout: Duration,
+    senders: HashMap<K, SenderQueue>,
+}
+
+/// How far behind the next expected number a `seq` must be to count as the
+/// sender restarting rather than a late arrival.
+pub const RESTART_GAP: u64 = 1024;
+
+#[derive(Default)]
+struct SenderQueue {
+    next: Option<u64>,
+    held: BTreeMap<u64, (Instant, DatasetData)>,
+}
+
+impl SenderQueue {
+    /// Take the held messages that are now in order.
+    fn drain_ready(&mut self, ready: &mut Vec<DatasetData>) {
+        while let Some(next) = self.next {
+            match self.held.remove(&next) {
+                Some((_, entry)) => {
+                    ready.push(entry);
+                    self.next = next.checked_add(1);
+                }
+                None => break,
+            }
+        }
+    }
+}
+
+impl<K: Hash + Eq + Clone> Sequencer<K> {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            senders: HashMap::new(),
+        }
+    }
+
+    /// Accept a received entry, returning every entry now ready to store.
+    pub fn accept(
+        &mut self,
+        sender: &K,
+        seq: Option<u64>,
+        entry: DatasetData,
+        now: Instant,
+    ) -> Vec<DatasetData> {
+        let Some(seq) = seq else {
+            return vec![entry];
+        };
+        let queue = self.senders.entry(sender.clone()).or_default();
+        let next = *queue.next.get_or_insert(seq);
+        if seq < next {
+            if next - seq <= RESTART_GAP {
+                // late after a skipped gap
+                return vec![entry];
+            }
+            // the sender restarted its count: release what the old count
+            // was waiting on, then continue from this message
+            let mut ready: Vec<_> = std::mem::take(&mut queue.held)
+                .into_values()
+                .map(|(_, entry)| entry)
+                .collect();
+            ready.push(entry);
+            queue.next = seq.checked_add(1);
+            return ready;
+        }
+        queue.held.insert(seq, (now, entry));
+        let mut ready = vec![];
+        queue.drain_ready(&mut ready);
+        ready
+    }
+
+    /// Give up on gaps whose following message has waited too long,
+    /// returning the entries released by doing so.
+    pub fn expire(&mut self, now: Instant) -> Vec<DatasetData> {
+        let mut ready = vec![];
+        for queue in self.senders.values_mut() {
+            while let Some((&first, (held_at, _))) = queue.held.iter().next() {
+                if now.duration_since(*held_at) < self.timeout {
+                    break;
+                }
+                queue.next = Some(first);
+                queue.drain_ready(&mut ready);
+            }
+        }
+        ready
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    fn msg(seq: u64) -> DatasetData {
+        DatasetData::String(seq.to_string())
+    }
+
+    /// The numbers of released entries, in release order.
+    fn seqs(entries: Vec<DatasetData>) -> Vec<u64> {
+        entries
+            .iter()
+            .map(|entry| match entry {
+                DatasetData::String(seq) => seq.parse().expect("entry is a number"),
+                entry => panic!("unexpected entry {:?}", entry),
+            })
+            .collect()
+    }
+
+    /// Feed `fed` from sender `key` in order, returning the numbers
+    /// released along the way.
+    fn feed_from(
+        sequencer: &mut Sequencer<&'static str>,
+        key: &'static str,
+        fed: &[u64],
+        now: Instant,
+    ) -> Vec<u64> {
+        let ready = fed
+            .iter()
+            .flat_map(|&seq| sequencer.accept(&key, Some(seq), msg(seq), now))
+            .collect();
+        seqs(ready)
+    }
+
+    fn feed(sequencer: &mut Sequencer<&'static str>, fed: &[u64], now: Instant) -> Vec<u64> {
+        feed_from(sequencer, "sender", fed, now)
+    }
+
+    #[test]
+    fn in_order_passes_straight_through() {
+        let mut sequencer = Sequencer::new(TIMEOUT);
+        let ready = feed(&mut sequencer, &[0, 1, 2, 3], Instant::now());
+        assert_eq!(ready, vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn out_of_order_is_held_until_the_gap_fills() {
+        let mut sequencer = Sequencer::new(TIMEOUT);
+        let now = Instant::now();
+        assert_eq!(feed(&mut sequencer, &[0, 2, 3], now), vec![0]);
+        assert_eq!(feed(&mut sequencer, &[1], now), vec![1, 2, 3]);
+        assert!(sequencer.expire(now + TIMEOUT).is_empty());
+    }
+
+    #[test]
+    fn gap_is_given_up_on_after_the_timeout() {
+        let mut sequencer = Sequencer::new(TIMEOUT);
+        let now = Instant::now();
+        assert_eq!(feed(&mut sequencer, &[0, 2, 3], now), vec![0]);
+        assert!(sequencer.expire(now + TIMEOUT / 2).is_empty());
+        assert_eq!(seqs(sequencer.expire(now + TIMEOUT)), vec![2, 3]);
+        assert_eq!(feed(&mut sequencer, &[4], now + TIMEOUT), vec![4]);
+    }
+
+    /// A message arriving after its gap was given up on must not move the
+    /// expected number back and hold up the ones after it.
+    #[test]
+    fn late_arrival_after_a_gap_does_not_rewind() {
+        let mut sequencer = Sequencer::new(TIMEOUT);
+        let now = Instant::now();
+        assert_eq!(feed(&mut sequencer, &[5], now), vec![5]);
+        assert_eq!(feed(&mut sequencer, &[4], now), vec![4]);
+        assert_eq!(feed(&mut sequencer, &[6, 7], now), vec![6, 7]);
+
+        assert_eq!(feed(&mut sequencer, &[9], now), Vec::<u64>::new());
+        assert_eq!(seqs(sequencer.expire(now + TIMEOUT)), vec![9]);
+        assert_eq!(feed(&mut sequencer, &[8], now), vec![8]);
+        assert_eq!(feed(&mut sequencer, &[10], now), vec![10]);
+    }
+
+    #[test]
+    fn large_step_back_is_a_restart() {
+        let mut sequencer = Sequencer::new(TIMEOUT);
+        let now = Instant::now();
+        let start = RESTART_GAP + 10;
+        assert_eq!(feed(&mut sequencer, &[start, start + 2], now), vec![start]);
+        assert_eq!(feed(&mut sequencer, &[0], now), vec![start + 2, 0]);
+        assert_eq!(feed(&mut sequencer, &[2], now), Vec::<u64>::new());
+        assert_eq!(feed(&mut sequencer, &[1], now), vec![1, 2]);
+    }
+
+    /// A sender that restarts counts from 0 under a new session, which must
+    /// be ordered on its own rather than passed through as late.
+    #[test]
+    fn short_restart_is_ordered_under_its_new_session() {
+        let mut sequencer = Sequencer::new(TIMEOUT);
+        let now = Instant::now();
+        assert_eq!(
+            feed_from(&mut sequencer, "first", &[0, 1, 2, 3, 4], now),
+            vec![0, 1, 2, 3, 4]
+        );
+        assert_eq!(feed_from(&mut sequencer, "second", &[0, 2], now), vec![0]);
+        assert_eq!(feed_from(&mut sequencer, "second", &[1], now), vec![1, 2]);
+    }
+
+    #[test]
+    fn count_past_the_end_does_not_overflow() {
+        let mut sequencer = Sequencer::new(TIMEOUT);
+        let now = Instant::now();
+        assert_eq!(
+            feed(&mut sequencer, &[u64::MAX - 1, u64::MAX], now),
+            vec![u64::MAX - 1, u64::MAX]
+        );
+        assert_eq!(feed(&mut sequencer, &[0, 1], now), vec![0, 1]);
+
+        let mut sequencer = Sequencer::new(TIMEOUT);
+        let start = RESTART_GAP + 1;
+        assert_eq!(feed(&mut sequencer, &[start, u64::MAX], now), vec![start]);
+        assert_eq!(feed(&mut sequencer, &[0], now), vec![u64::MAX, 0]);
+    }
+}
@@ -0,0 +1,110 @@
+use std::{
+    io::{self, IsTerminal},
+    sync::atomic::{AtomicBool, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde_json::json;
+use spider_client::{message::DatasetData, Relation};
+
+use crate::relation;
+
+/// Set by `--output json`. Stdout then carries only received events, one
+/// JSON object per line, and every other message goes to stderr.
+static JSON: AtomicBool = AtomicBool::new(false);
+static COLOR: AtomicBool = AtomicBool::new(false);
+
+/// When log lines are colored, from `--color`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ColorMode {
+    /// Only when logging to a terminal.
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorMode {
+    pub fn from_tag(tag: &str) -> Option<Self> {
+        match tag {
+            "auto" => Some(ColorMode::Auto),
+            "always" => Some(ColorMode::Always),
+            "never" => Some(ColorMode::Never),
+            _ => None,
+        }
+    }
+}
+
+/// What a log line is about, which picks its color.
+pub enum Tone {
+    Error,
+    /// Approval and denial.
+    Auth,
+    Event,
+    /// Recipient tags.
+    Label,
+}
+
+pub fn set_json(json: bool) {
+    JSON.store(json, Ordering::Relaxed);
+}
+
+pub fn is_json() -> bool {
+    JSON.load(Ordering::Relaxed)
+}
+
+/// Call after [`set_json`], since that decides which stream logs go to.
+pub fn set_color(mode: ColorMode) {
+    let color = match mode {
+        ColorMode::Always => true,
+        ColorMode::Never => false,
+        ColorMode::Auto if is_json() => io::stderr().is_terminal(),
+        ColorMode::Auto => io::stdout().is_terminal(),
+    };
+    COLOR.store(color, Ordering::Relaxed);
+}
+
+pub fn paint(tone: Tone, text: String) -> String {
+    if !COLOR.load(Ordering::Relaxed) {
+        return text;
+    }
+    let code = match tone {
+        Tone::Error => 31,
+        Tone::Auth => 33,
+        Tone::Event => 32,
+        Tone::Label => 36,
+    };
+    format!("\x1b[{}m{}\x1b[0m", code, text)
+}
+
+/// Print a log line to stdout, or to stderr in JSON output mode. A leading
+/// `Tone;` colors the line, e.g. `log!(Error; "failed: {}", e)`.
+macro_rules! log {
+    ($tone:ident; $($arg:tt)*) => {
+        log!("{}", $crate::output::paint($crate::output::Tone::$tone, format!($($arg)*)))
+    };
+    ($($arg:tt)*) => {
+        if $crate::output::is_json() {
+            eprintln!($($arg)*);
+        } else {
+            println!($($arg)*);
+        }
+    }
+}
+
+/// Print a received event as a JSON line, if in JSON output mode.
+pub fn event(name: &str, from: &Relation, data: &DatasetData) {
+    if !is_json() {
+        return;
+    }
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default();
+    let line = json!({
+        "timestamp": timestamp,
+        "event": name,
+        "sender": relation::display(from),
+        "payload": data,
+    });
+    println!("{}", line);
+}
@@ -0,0 +1,379 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+};
+
+use argon2::Argon2;
+use chacha20poly1305::{
+    aead::{Aead, KeyInit},
+    Key, XChaCha20Poly1305, XNonce,
+};
+use spider_client::message::DatasetData;
+use tokio::task::JoinHandle;
+
+/// Leads every encrypted cache file, followed by the salt, the nonce and
+/// the ciphertext.
+const MAGIC: &[u8; 4] = b"TRC1";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+
+/// How cached datasets are written to disk.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum PersistFormat {
+    Json,
+    Cbor,
+}
+
+impl PersistFormat {
+    pub fn from_tag(tag: &str) -> Option<Self> {
+        match tag {
+            "json" => Some(PersistFormat::Json),
+            "cbor" => Some(PersistFormat::Cbor),
+            _ => None,
+        }
+    }
+
+    fn encode(self, entries: &[DatasetData]) -> Result<Vec<u8>, io::Error> {
+        match self {
+            PersistFormat::Json => serde_json::to_vec(entries).map_err(invalid_data),
+            PersistFormat::Cbor => serde_cbor::to_vec(entries).map_err(invalid_data),
+        }
+    }
+}
+
+/// Local copies of the Recp and Messages datasets, kept in a directory so
+/// they are available at startup before the router sends the real ones.
+///
+/// Files are written in the configured format and read back in either: a
+/// JSON cache always starts with `[`, which a CBOR array never does. With a
+/// passphrase the encoded bytes are encrypted with XChaCha20-Poly1305 under
+/// a key derived by Argon2, and encrypted files are recognised by [`MAGIC`].
+/// Argon2 is slow by design, so the key is derived once, when the cache is
+/// opened, and each write only draws a fresh nonce.
+///
+/// Every dataset echo saves, so saves only record the latest entries and
+/// [`Cache::flush`] writes them off the event loop, at most one write at a
+/// time.
+pub struct Cache {
+    writer: Writer,
+    /// For decrypting caches written under another salt.
+    passphrase: Option<String>,
+    /// Entries saved since the last flush, by dataset name.
+    pending: BTreeMap<String, Vec<DatasetData>>,
+    /// The write started by the last flush.
+    writing: Option<JoinHandle<()>>,
+}
+
+impl Cache {
+    pub fn new(
+        dir: PathBuf,
+        format: PersistFormat,
+        passphrase: Option<String>,
+    ) -> Result<Self, io::Error> {
+        let sealer = match &passphrase {
+            Some(passphrase) => Some(Sealer::new(passphrase, &dir)?),
+            None => None,
+        };
+        Ok(Self {
+            writer: Writer {
+                dir,
+                format,
+                sealer,
+            },
+            passphrase,
+            pending: BTreeMap::new(),
+            writing: None,
+        })
+    }
+
+    /// The cached entries for `name`, or none if there is no usable cache.
+    pub fn load(&self, name: &str) -> Vec<DatasetData> {
+        let path = self.writer.path(name);
+        match read(
+            &path,
+            self.passphrase.as_deref(),
+            self.writer.sealer.as_ref(),
+        ) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => vec![],
+            Err(e) => {
+                log!("Ignoring cache {}: {}", path.display(), e);
+                vec![]
+            }
+        }
+    }
+
+    /// Replace the cached entries for `name` at the next flush.
+    pub fn save(&mut self, name: &str, entries: &[DatasetData]) {
+        self.pending.insert(name.to_string(), entries.to_vec());
+    }
+
+    /// Start writing the saved entries in the background, unless the last
+    /// write is still running, in which case they wait for the next flush.
+    pub fn flush(&mut self) {
+        let busy = self
+            .writing
+            .as_ref()
+            .is_some_and(|writing| !writing.is_finished());
+        if busy || self.pending.is_empty() {
+            return;
+        }
+        let pending = std::mem::take(&mut self.pending);
+        let writer = self.writer.clone();
+        self.writing = Some(tokio::task::spawn_blocking(move || {
+            for (name, entries) in pending {
+                writer.write(&name, &entries);
+            }
+        }));
+    }
+
+    /// Wait for the running write and write whatever is still saved, so
+    /// nothing is lost on shutdown.
+    pub async fn finish(&mut self) {
+        if let Some(writing) = self.writing.take() {
+            let _ = writing.await;
+        }
+        for (name, entries) in std::mem::take(&mut self.pending) {
+            self.writer.write(&name, &entries);
+        }
+    }
+}
+
+/// What a cache write needs, split out so it can move to a blocking task.
+#[derive(Clone)]
+struct Writer {
+    dir: PathBuf,
+    format: PersistFormat,
+    sealer: Option<Sealer>,
+}
+
+impl Writer {
+    fn write(&self, name: &str, entries: &[DatasetData]) {
+        let path = self.path(name);
+        let result = fs::create_dir_all(&self.dir)
+            .and_then(|()| self.format.encode(entries))
+            .and_then(|bytes| match &self.sealer {
+                Some(sealer) => sealer.seal(&bytes),
+                None => Ok(bytes),
+            })
+            .and_then(|bytes| fs::write(&path, bytes));
+        if let Err(e) = result {
+            log!(Error; "Failed to write cache {}: {}", path.display(), e);
+        }
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        cache_path(&self.dir, name)
+    }
+}
+
+fn cache_path(dir: &Path, name: &str) -> PathBuf {
+    dir.join(format!("{}.cache", name))
+}
+
+/// The key encrypted caches are written under, with the salt it was
+/// derived from.
+#[derive(Clone)]
+struct Sealer {
+    salt: [u8; SALT_LEN],
+    cipher: XChaCha20Poly1305,
+}
+
+impl Sealer {
+    /// Derive the key for `passphrase`. The salt of an existing encrypted
+    /// cache in `dir` is reused, so loading it needs no second derivation.
+    fn new(passphrase: &str, dir: &Path) -> Result<Self, io::Error> {
+        let existing = ["recps", "msgs"]
+            .iter()
+            .find_map(|name| salt_of(&cache_path(dir, name)));
+        let salt = match existing {
+            Some(salt) => salt,
+            None => {
+                let mut salt = [0; SALT_LEN];
+                getrandom::getrandom(&mut salt).map_err(invalid_data)?;
+                salt
+            }
+        };
+        Ok(Self {
+            salt,
+            cipher: cipher(passphrase, &salt)?,
+        })
+    }
+
+    fn seal(&self, plaintext: &[u8]) -> Result<Vec<u8>, io::Error> {
+        let mut nonce = [0; NONCE_LEN];
+        getrandom::getrandom(&mut nonce).map_err(invalid_data)?;
+        let ciphertext = self
+            .cipher
+            .encrypt(XNonce::from_slice(&nonce), plaintext)
+            .map_err(|_| invalid_data_msg("encryption failed"))?;
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&self.salt);
+        bytes.extend_from_slice(&nonce);
+        bytes.extend(ciphertext);
+        Ok(bytes)
+    }
+}
+
+/// The salt in the header of the encrypted cache at `path`, if it is one.
+fn salt_of(path: &Path) -> Option<[u8; SALT_LEN]> {
+    let mut header = [0; MAGIC.len() + SALT_LEN];
+    fs::File::open(path).ok()?.read_exact(&mut header).ok()?;
+    if !header.starts_with(MAGIC) {
+        return None;
+    }
+    header[MAGIC.len()..].try_into().ok()
+}
+
+fn read(
+    path: &Path,
+    passphrase: Option<&str>,
+    sealer: Option<&Sealer>,
+) -> Result<Vec<DatasetData>, io::Error> {
+    let mut bytes = fs::read(path)?;
+    if bytes.starts_with(MAGIC) {
+        let passphrase = passphrase.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                "cache is encrypted, no passphrase set",
+            )
+        })?;
+        bytes = decrypt(passphrase, sealer, &bytes)?;
+    }
+    if bytes.first() == Some(&b'[') {
+        serde_json::from_slice(&bytes).map_err(invalid_data)
+    } else {
+        serde_cbor::from_slice(&bytes).map_err(invalid_data)
+    }
+}
+
+fn decrypt(passphrase: &str, sealer: Option<&Sealer>, bytes: &[u8]) -> Result<Vec<u8>, io::Error> {
+    let header = MAGIC.len() + SALT_LEN + NONCE_LEN;
+    if bytes.len() < header {
+        return Err(invalid_data_msg("encrypted cache is truncated"));
+    }
+    let (salt, rest) = bytes[MAGIC.len()..].split_at(SALT_LEN);
+    let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+    let aead = match sealer {
+        Some(sealer) if sealer.salt == salt => sealer.cipher.clone(),
+        _ => cipher(passphrase, salt)?,
+    };
+    aead.decrypt(XNonce::from_slice(nonce), ciphertext)
+        .map_err(|_| invalid_data_msg("wrong passphrase or corrupted cache"))
+}
+
+fn cipher(passphrase: &str, salt: &[u8]) -> Result<XChaCha20Poly1305, io::Error> {
+    let mut key = [0; 32];
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| invalid_data_msg(&e.to_string()))?;
+    Ok(XChaCha20Poly1305::new(Key::from_slice(&key)))
+}
+
+fn invalid_data_msg(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+fn invalid_data(e: impl std::error::Error + Send + Sync + 'static) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh directory under the system temp dir for one test.
+    fn temp_dir(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "test_router_persist_{}_{}",
+            test,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn entries() -> Vec<DatasetData> {
+        let mut map = BTreeMap::new();
+        map.insert(
+            String::from("text"),
+            DatasetData::String(String::from("hi")),
+        );
+        map.insert(
+            String::from("sender"),
+            DatasetData::String(String::from("peer:abc")),
+        );
+        vec![
+            DatasetData::String(String::from("peer:abc")),
+            DatasetData::Map(map),
+        ]
+    }
+
+    fn round_trip(test: &str, format: PersistFormat, passphrase: Option<&str>) -> Vec<DatasetData> {
+        let dir = temp_dir(test);
+        let passphrase = passphrase.map(str::to_string);
+        let cache = Cache::new(dir.clone(), format, passphrase).unwrap();
+        cache.writer.write("msgs", &entries());
+        let loaded = cache.load("msgs");
+        let _ = fs::remove_dir_all(dir);
+        loaded
+    }
+
+    #[test]
+    fn json_round_trips() {
+        let loaded = round_trip("json", PersistFormat::Json, None);
+        assert_eq!(format!("{:?}", loaded), format!("{:?}", entries()));
+    }
+
+    #[test]
+    fn cbor_round_trips() {
+        let loaded = round_trip("cbor", PersistFormat::Cbor, None);
+        assert_eq!(format!("{:?}", loaded), format!("{:?}", entries()));
+    }
+
+    #[test]
+    fn missing_cache_loads_empty() {
+        let cache = Cache::new(temp_dir("missing"), PersistFormat::Json, None).unwrap();
+        assert!(cache.load("msgs").is_empty());
+    }
+
+    #[test]
+    fn encrypted_round_trips() {
+        let loaded = round_trip("encrypted", PersistFormat::Cbor, Some("secret"));
+        assert_eq!(format!("{:?}", loaded), format!("{:?}", entries()));
+    }
+
+    #[test]
+    fn reopening_reuses_the_salt_and_reads_back() {
+        let dir = temp_dir("reopen");
+        let passphrase = Some(String::from("secret"));
+        let first = Cache::new(dir.clone(), PersistFormat::Json, passphrase.clone()).unwrap();
+        first.writer.write("recps", &entries());
+        let second = Cache::new(dir.clone(), PersistFormat::Json, passphrase).unwrap();
+        let salts = |cache: &Cache| cache.writer.sealer.as_ref().map(|sealer| sealer.salt);
+        assert_eq!(salts(&first), salts(&second));
+        let loaded = second.load("recps");
+        let _ = fs::remove_dir_all(dir);
+        assert_eq!(format!("{:?}", loaded), format!("{:?}", entries()));
+    }
+
+    #[test]
+    fn wrong_passphrase_loads_nothing() {
+        let dir = temp_dir("wrong");
+        let cache = Cache::new(
+            dir.clone(),
+            PersistFormat::Cbor,
+            Some(String::from("right")),
+        )
+        .unwrap();
+        cache.writer.write("msgs", &entries());
+        let path = cache.writer.path("msgs");
+        let result = read(&path, Some("wrong"), None);
+        let unencrypted = read(&path, None, None);
+        let _ = fs::remove_dir_all(dir);
+        assert!(result.is_err());
+        assert!(unencrypted.is_err());
+    }
+}
@@ -0,0 +1,70 @@
+use std::time::{Duration, Instant};
+
+use spider_client::Relation;
+
+use crate::{directory::Directory, relation};
+
+/// A reachability test of every recipient. Each is sent a `ping` carrying
+/// the batch id, and those that answer with a `pong` before the deadline
+/// are reported alive along with their round-trip time.
+pub struct PingBatch {
+    id: String,
+    sent_at: Instant,
+    deadline: Instant,
+    results: Vec<(Relation, Option<Duration>)>,
+}
+
+impl PingBatch {
+    pub fn new(id: String, recps: Vec<Relation>, timeout: Duration, sent_at: Instant) -> Self {
+        Self {
+            id,
+            sent_at,
+            deadline: sent_at + timeout,
+            results: recps.into_iter().map(|recp| (recp, None)).collect(),
+        }
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Each recipient with its round-trip time, if it answered.
+    pub fn results(&self) -> &[(Relation, Option<Duration>)] {
+        &self.results
+    }
+
+    /// Record a pong. Returns whether it answered this batch.
+    pub fn record(&mut self, id: &str, from: &Relation, now: Instant) -> bool {
+        if id != self.id {
+            return false;
+        }
+        match self.results.iter_mut().find(|(recp, _)| recp == from) {
+            Some((_, rtt @ None)) => {
+                *rtt = Some(now.duration_since(self.sent_at));
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether every recipient has answered or the deadline has passed.
+    pub fn is_done(&self, now: Instant) -> bool {
+        now >= self.deadline || self.results.iter().all(|(_, rtt)| rtt.is_some())
+    }
+
+    pub fn render(&self, directory: &Directory) -> String {
+        let alive = self.results.iter().filter(|(_, rtt)| rtt.is_some()).count();
+        let mut lines = vec![format!("{}/{} reachable", alive, self.results.len())];
+        for (recp, rtt) in &self.results {
+            let name = match directory.name_of(recp) {
+                Some(name) => format!("{} ({})", name, relation::display(recp)),
+                None => relation::display(recp),
+            };
+            match rtt {
+                Some(rtt) => lines.push(format!("{}: {}ms", name, rtt.as_millis())),
+                None => lines.push(format!("{}: UNREACHABLE", name)),
+            }
+        }
+        lines.join("\n")
+    }
+}
@@ -0,0 +1,44 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use spider_client::Relation;
+
+use crate::relation;
+
+/// Save a recipient list as `<dir>/<name>.json`, a JSON array of relations
+/// in the `peer:`/`peripheral:` form.
+pub fn save(dir: &Path, name: &str, recps: &[Relation]) -> Result<PathBuf, io::Error> {
+    let path = path(dir, name)?;
+    let recps: Vec<String> = recps.iter().map(relation::display).collect();
+    let json = serde_json::to_string_pretty(&recps)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    fs::create_dir_all(dir)?;
+    fs::write(&path, json)?;
+    Ok(path)
+}
+
+/// Read a recipient list written by [`save`].
+pub fn load(dir: &Path, name: &str) -> Result<Vec<Relation>, io::Error> {
+    let contents = fs::read_to_string(path(dir, name)?)?;
+    let recps: Vec<String> = serde_json::from_str(&contents)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    recps
+        .iter()
+        .map(|recp| {
+            relation::parse(recp).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+        })
+        .collect()
+}
+
+fn path(dir: &Path, name: &str) -> Result<PathBuf, io::Error> {
+    let name = name.trim();
+    if name.is_empty() || name.contains(['/', '\\']) || name.starts_with('.') {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid preset name {:?}", name),
+        ));
+    }
+    Ok(dir.join(format!("{}.json", name)))
+}
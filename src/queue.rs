@@ -0,0 +1,53 @@
+use std::{collections::BTreeMap, time::Duration};
+
+use spider_client::Relation;
+
+/// How soon a queued send goes out relative to the others.
+#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    Urgent,
+    #[default]
+    Normal,
+}
+
+/// Per-message settings chosen in the Send field.
+#[derive(Clone, Copy, Default)]
+pub struct SendOptions {
+    pub priority: Priority,
+    /// Receivers delete the message once it is this old.
+    pub ttl: Option<Duration>,
+}
+
+/// Typed messages waiting for their turn under `--send-rate`.
+///
+/// Sends leave in priority order, and in the order they were queued within
+/// the same priority.
+#[derive(Default)]
+pub struct SendQueue {
+    next_seq: u64,
+    sends: BTreeMap<(Priority, u64), (String, Vec<Relation>, SendOptions)>,
+}
+
+impl SendQueue {
+    pub fn push(&mut self, opts: SendOptions, text: String, recps: Vec<Relation>) {
+        self.sends
+            .insert((opts.priority, self.next_seq), (text, recps, opts));
+        self.next_seq += 1;
+    }
+
+    /// Remove and return up to `count` sends, highest priority first.
+    pub fn pop(&mut self, count: usize) -> Vec<(String, Vec<Relation>, SendOptions)> {
+        let mut sends = vec![];
+        while sends.len() < count {
+            match self.sends.pop_first() {
+                Some((_, send)) => sends.push(send),
+                None => break,
+            }
+        }
+        sends
+    }
+
+    pub fn len(&self) -> usize {
+        self.sends.len()
+    }
+}
@@ -0,0 +1,150 @@
+use std::collections::HashSet;
+
+use spider_client::message::DatasetData;
+
+use crate::messages;
+
+/// Keeps the Messages dataset whole across a reconnect.
+///
+/// Appends sent just before the connection dropped may or may not have
+/// reached the router, so every appended entry with an id is held until a
+/// Messages update shows it. After reconnecting, incoming messages are
+/// buffered until the first update arrives; that update is then compared
+/// by sender and id to find entries to restore and duplicates to delete.
+#[derive(Default)]
+pub struct Reconciler {
+    unconfirmed: Vec<DatasetData>,
+    reconnecting: bool,
+    buffered: Vec<DatasetData>,
+}
+
+/// The outcome of comparing a Messages update after a reconnect.
+#[derive(Default)]
+pub struct Reconciled {
+    /// Entries to append because the dataset lacks them.
+    pub missing: Vec<DatasetData>,
+    /// Indices of repeated entries, highest first so deleting them in order
+    /// keeps the remaining indices valid.
+    pub duplicates: Vec<usize>,
+}
+
+impl Reconciled {
+    /// Adjust for `trimmed` deletes of the oldest entries that go ahead of
+    /// these. Duplicates among those entries are already being removed; the
+    /// rest move down by `trimmed`.
+    pub fn after_trim(mut self, trimmed: usize) -> Self {
+        self.duplicates = self
+            .duplicates
+            .into_iter()
+            .filter_map(|idx| idx.checked_sub(trimmed))
+            .collect();
+        self
+    }
+}
+
+impl Reconciler {
+    /// Start buffering after the connection has been replaced.
+    pub fn begin(&mut self) {
+        self.reconnecting = true;
+    }
+
+    /// Hold `entry` until the reconnect completes, returning it back if no
+    /// reconnect is in progress.
+    pub fn buffer(&mut self, entry: DatasetData) -> Option<DatasetData> {
+        if self.reconnecting {
+            self.buffered.push(entry);
+            None
+        } else {
+            Some(entry)
+        }
+    }
+
+    /// Record an entry sent to the Messages dataset.
+    pub fn appended(&mut self, entry: &DatasetData) {
+        if key(entry).is_some() {
+            self.unconfirmed.push(entry.clone());
+        }
+    }
+
+    /// Handle a full Messages update. Confirms the entries it contains and,
+    /// if this is the first update since a reconnect, reports what to fix.
+    pub fn update(&mut self, msgs: &[DatasetData]) -> Reconciled {
+        let mut seen = HashSet::new();
+        let mut duplicates = vec![];
+        for (idx, entry) in msgs.iter().enumerate() {
+            if let Some(key) = key(entry) {
+                if !seen.insert(key) {
+                    duplicates.push(idx);
+                }
+            }
+        }
+        self.unconfirmed
+            .retain(|entry| key(entry).is_some_and(|key| !seen.contains(&key)));
+
+        if !self.reconnecting {
+            return Reconciled::default();
+        }
+        self.reconnecting = false;
+        duplicates.reverse();
+        let mut missing = std::mem::take(&mut self.unconfirmed);
+        for entry in std::mem::take(&mut self.buffered) {
+            match key(&entry) {
+                Some(key) if seen.contains(&key) => {}
+                _ => missing.push(entry),
+            }
+        }
+        Reconciled {
+            missing,
+            duplicates,
+        }
+    }
+}
+
+fn key(entry: &DatasetData) -> Option<String> {
+    let id = messages::id(entry)?;
+    let sender = match entry {
+        DatasetData::Map(map) => match map.get("sender") {
+            Some(DatasetData::String(sender)) => sender.as_str(),
+            _ => "",
+        },
+        _ => "",
+    };
+    Some(format!("{}/{}", sender, id))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::trim::Trimmer;
+
+    fn entry(id: &str) -> DatasetData {
+        let mut map = BTreeMap::new();
+        map.insert(String::from("id"), DatasetData::String(id.to_string()));
+        DatasetData::Map(map)
+    }
+
+    /// An echo past the cap right after a reconnect: the trim deletes go
+    /// first, so the duplicate deletes must land on the trimmed dataset.
+    #[test]
+    fn duplicates_are_deleted_after_an_over_cap_trim() {
+        let ids = ["a", "b", "a", "c", "d", "b", "e", "f"];
+        let mut msgs: Vec<DatasetData> = ids.into_iter().map(entry).collect();
+        let mut reconciler = Reconciler::default();
+        let mut trimmer = Trimmer::new(5);
+        reconciler.begin();
+
+        let reconciled = reconciler.update(&msgs);
+        for _ in 0..trimmer.update(msgs.len()) {
+            msgs.remove(0);
+        }
+        let reconciled = reconciled.after_trim(trimmer.in_flight());
+        for idx in reconciled.duplicates {
+            msgs.remove(idx);
+        }
+
+        let left: Vec<_> = msgs.iter().filter_map(messages::id).collect();
+        assert_eq!(left, vec!["c", "d", "e", "f"]);
+    }
+}
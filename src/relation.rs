@@ -0,0 +1,30 @@
+use spider_client::{Relation, Role};
+
+/// Parse a recipient written as `<base64>`, `peer:<base64>` or
+/// `peripheral:<base64>`. A bare relation is treated as a peer.
+pub fn parse(text: &str) -> Result<Relation, String> {
+    let text = text.trim();
+    let (role, encoded) = match text.split_once(':') {
+        None => (Role::Peer, text),
+        Some(("peer", encoded)) => (Role::Peer, encoded),
+        Some(("peripheral", encoded)) => (Role::Peripheral, encoded),
+        Some((prefix, _)) => return Err(format!("unsupported relation kind: {}", prefix)),
+    };
+    let mut relation = Relation::peer_from_base_64(encoded)
+        .ok_or_else(|| format!("invalid relation: {}", encoded))?;
+    relation.role = role;
+    Ok(relation)
+}
+
+/// The base-64 form of a relation's id, without a role prefix.
+pub fn to_base64(relation: &Relation) -> String {
+    relation.id.to_base64()
+}
+
+/// The prefixed form accepted by [`parse`].
+pub fn display(relation: &Relation) -> String {
+    match relation.role {
+        Role::Peer => format!("peer:{}", to_base64(relation)),
+        Role::Peripheral => format!("peripheral:{}", to_base64(relation)),
+    }
+}
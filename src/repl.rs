@@ -0,0 +1,122 @@
+use tokio::{
+    io::{self, AsyncBufReadExt, BufReader},
+    sync::mpsc,
+};
+
+const HELP: &str = "\
+commands:
+  add [peer:|peripheral:]<base64>
+  rm <idx>
+  mute <idx>
+  move <from> <to>
+  tag <idx> <tag>
+  send <text>
+  resend
+  confirm
+  pause
+  whoami
+  caps
+  testall
+  sendfile <path>
+  import <name pattern>
+  schedule <+30s|@unix>: <text>
+  cancel <id>
+  recps
+  clearrecps
+  save <preset>
+  load <preset>
+  msgs
+  dump
+  stats
+  switch <recp|msgs> <name>
+  quit";
+
+/// A command typed on stdin.
+pub enum Command {
+    Add(String),
+    Remove(usize),
+    Mute(usize),
+    Move(String),
+    Tag(String),
+    Send(String),
+    Resend,
+    Confirm,
+    Pause,
+    Whoami,
+    Caps,
+    TestAll,
+    SendFile(String),
+    Import(String),
+    Schedule(String),
+    Cancel(String),
+    Recps,
+    ClearRecps,
+    SavePreset(String),
+    LoadPreset(String),
+    Msgs,
+    Dump,
+    Stats,
+    Switch(String),
+    Quit,
+}
+
+impl Command {
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let line = line.trim();
+        let (name, rest) = match line.split_once(' ') {
+            Some((name, rest)) => (name, rest.trim()),
+            None => (line, ""),
+        };
+        match name {
+            "add" if !rest.is_empty() => Ok(Command::Add(rest.to_string())),
+            "rm" => rest
+                .parse()
+                .map(Command::Remove)
+                .map_err(|_| format!("rm expects an index, got {:?}", rest)),
+            "mute" => rest
+                .parse()
+                .map(Command::Mute)
+                .map_err(|_| format!("mute expects an index, got {:?}", rest)),
+            "move" if !rest.is_empty() => Ok(Command::Move(rest.to_string())),
+            "tag" if !rest.is_empty() => Ok(Command::Tag(rest.to_string())),
+            "send" if !rest.is_empty() => Ok(Command::Send(rest.to_string())),
+            "resend" => Ok(Command::Resend),
+            "confirm" => Ok(Command::Confirm),
+            "pause" => Ok(Command::Pause),
+            "whoami" => Ok(Command::Whoami),
+            "caps" => Ok(Command::Caps),
+            "testall" => Ok(Command::TestAll),
+            "sendfile" if !rest.is_empty() => Ok(Command::SendFile(rest.to_string())),
+            "import" if !rest.is_empty() => Ok(Command::Import(rest.to_string())),
+            "schedule" if !rest.is_empty() => Ok(Command::Schedule(rest.to_string())),
+            "cancel" if !rest.is_empty() => Ok(Command::Cancel(rest.to_string())),
+            "recps" => Ok(Command::Recps),
+            "clearrecps" => Ok(Command::ClearRecps),
+            "save" if !rest.is_empty() => Ok(Command::SavePreset(rest.to_string())),
+            "load" if !rest.is_empty() => Ok(Command::LoadPreset(rest.to_string())),
+            "msgs" => Ok(Command::Msgs),
+            "dump" => Ok(Command::Dump),
+            "stats" => Ok(Command::Stats),
+            "switch" if !rest.is_empty() => Ok(Command::Switch(rest.to_string())),
+            "quit" => Ok(Command::Quit),
+            _ => Err(format!("unknown command {:?}\n{}", line, HELP)),
+        }
+    }
+}
+
+/// Forward lines from stdin to the main loop until stdin closes.
+pub fn spawn_stdin_reader() -> mpsc::Receiver<String> {
+    let (tx, rx) = mpsc::channel(16);
+    tokio::spawn(async move {
+        let mut lines = BufReader::new(io::stdin()).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            if line.trim().is_empty() {
+                continue;
+            }
+            if tx.send(line).await.is_err() {
+                break;
+            }
+        }
+    });
+    rx
+}
@@ -0,0 +1,43 @@
+use std::collections::VecDeque;
+
+use crate::event_log::now_ms;
+
+/// The last few raw messages received, of every kind, for dumping after
+/// something unexpected happens. Once full, the oldest message is dropped.
+pub struct MessageRing {
+    capacity: usize,
+    entries: VecDeque<(u64, String)>,
+}
+
+impl MessageRing {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: VecDeque::new(),
+        }
+    }
+
+    pub fn record(&mut self, msg: String) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() >= self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back((now_ms(), msg));
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// One line per message, oldest first, prefixed with its receive time
+    /// in milliseconds since the epoch.
+    pub fn render(&self) -> String {
+        let mut text = String::new();
+        for (at_ms, msg) in &self.entries {
+            text.push_str(&format!("{} {}\n", at_ms, msg));
+        }
+        text
+    }
+}
@@ -0,0 +1,90 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Size limits for a [`RotatingFile`].
+#[derive(Clone, Copy)]
+pub struct Rotation {
+    /// Rotate once the file grows past this many bytes.
+    pub max_bytes: u64,
+    /// How many rotated files are kept beside the live one.
+    pub keep: usize,
+}
+
+/// An append-only file that is renamed to `<name>.<millis>` once it grows
+/// past the configured size, keeping at most `keep` rotated copies.
+pub struct RotatingFile {
+    path: PathBuf,
+    rotation: Option<Rotation>,
+    file: File,
+    len: u64,
+}
+
+impl RotatingFile {
+    pub fn open(path: &Path, rotation: Option<Rotation>) -> Result<Self, io::Error> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let len = file.metadata()?.len();
+        Ok(Self {
+            path: path.to_path_buf(),
+            rotation,
+            file,
+            len,
+        })
+    }
+
+    pub fn write_line(&mut self, line: &str) -> Result<(), io::Error> {
+        writeln!(self.file, "{}", line)?;
+        self.len += line.len() as u64 + 1;
+        if let Some(rotation) = self.rotation {
+            if self.len > rotation.max_bytes {
+                self.rotate(rotation)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn rotate(&mut self, rotation: Rotation) -> Result<(), io::Error> {
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or_default();
+        let mut rotated = self.path.clone().into_os_string();
+        rotated.push(format!(".{}", millis));
+        fs::rename(&self.path, &rotated)?;
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        self.len = 0;
+        self.prune(rotation.keep)
+    }
+
+    /// Delete the oldest rotated files beyond `keep`.
+    fn prune(&self, keep: usize) -> Result<(), io::Error> {
+        let Some(name) = self.path.file_name().and_then(|name| name.to_str()) else {
+            return Ok(());
+        };
+        let prefix = format!("{}.", name);
+        let dir = match self.path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let mut rotated: Vec<(u128, PathBuf)> = fs::read_dir(dir)?
+            .filter_map(Result::ok)
+            .filter_map(|entry| {
+                let file_name = entry.file_name();
+                let stamp = file_name.to_str()?.strip_prefix(&prefix)?.parse().ok()?;
+                Some((stamp, entry.path()))
+            })
+            .collect();
+        rotated.sort();
+        let excess = rotated.len().saturating_sub(keep);
+        for (_, path) in rotated.into_iter().take(excess) {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+}
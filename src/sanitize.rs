@@ -0,0 +1,101 @@
+/// What to do with input containing control characters.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum SanitizePolicy {
+    /// Remove control characters and ANSI escape sequences.
+    Strip,
+    /// Refuse the input entirely.
+    Reject,
+}
+
+/// Clean user input of control characters and ANSI escape sequences so it
+/// cannot corrupt the message view or the terminal. Line breaks are kept.
+pub fn sanitize(text: &str, policy: SanitizePolicy) -> Result<String, String> {
+    if !text.chars().any(is_unsafe) {
+        return Ok(text.to_string());
+    }
+    if policy == SanitizePolicy::Reject {
+        return Err(String::from("input contains control characters"));
+    }
+
+    let mut clean = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\u{1b}' {
+            // CSI sequences run from `ESC [` to a final byte in '@'..='~'
+            if chars.peek() == Some(&'[') {
+                chars.next();
+                for c in chars.by_ref() {
+                    if ('@'..='~').contains(&c) {
+                        break;
+                    }
+                }
+            }
+        } else if !is_unsafe(c) {
+            clean.push(c);
+        }
+    }
+    Ok(clean)
+}
+
+fn is_unsafe(c: char) -> bool {
+    c.is_control() && c != '\n'
+}
+
+/// Turn `\n` in typed input into a line break, and `\\` into a backslash,
+/// since text entries only take a single line.
+pub fn expand_newlines(text: &str) -> String {
+    let mut expanded = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('\\', Some('n')) => {
+                chars.next();
+                expanded.push('\n');
+            }
+            ('\\', Some('\\')) => {
+                chars.next();
+                expanded.push('\\');
+            }
+            _ => expanded.push(c),
+        }
+    }
+    expanded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips_csi_sequences_and_control_characters() {
+        let text = "\u{1b}[31mred\u{1b}[0m text\u{7}";
+        assert_eq!(sanitize(text, SanitizePolicy::Strip).unwrap(), "red text");
+    }
+
+    #[test]
+    fn rejects_control_characters_under_reject() {
+        assert!(sanitize("bell\u{7}", SanitizePolicy::Reject).is_err());
+        assert_eq!(
+            sanitize("plain text", SanitizePolicy::Reject).unwrap(),
+            "plain text"
+        );
+    }
+
+    #[test]
+    fn keeps_line_breaks() {
+        let text = "first\nsecond";
+        assert_eq!(sanitize(text, SanitizePolicy::Strip).unwrap(), text);
+        assert_eq!(sanitize(text, SanitizePolicy::Reject).unwrap(), text);
+        assert_eq!(
+            sanitize("first\r\nsecond", SanitizePolicy::Strip).unwrap(),
+            "first\nsecond"
+        );
+    }
+
+    #[test]
+    fn expands_escaped_newlines() {
+        assert_eq!(expand_newlines(r"one\ntwo"), "one\ntwo");
+        assert_eq!(expand_newlines(r"one\\ntwo"), r"one\ntwo");
+        assert_eq!(expand_newlines(r"trailing\"), r"trailing\");
+    }
+}
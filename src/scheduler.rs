@@ -0,0 +1,88 @@
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+    time::{Duration, Instant},
+};
+
+use crate::config::parse_duration;
+
+/// Messages waiting to be sent at a later time.
+///
+/// Cancelled entries are removed from `texts` and skipped lazily when they
+/// reach the top of the heap.
+#[derive(Default)]
+pub struct Scheduler {
+    next_id: u64,
+    heap: BinaryHeap<Reverse<(Instant, u64)>>,
+    texts: HashMap<u64, String>,
+}
+
+impl Scheduler {
+    pub fn schedule(&mut self, due: Instant, text: String) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.heap.push(Reverse((due, id)));
+        self.texts.insert(id, text);
+        id
+    }
+
+    pub fn cancel(&mut self, id: u64) -> bool {
+        self.texts.remove(&id).is_some()
+    }
+
+    /// Remove and return every message due at or before `now`, in due order.
+    pub fn pop_due(&mut self, now: Instant) -> Vec<String> {
+        let mut due = vec![];
+        while let Some(Reverse((at, id))) = self.heap.peek().copied() {
+            if at > now {
+                break;
+            }
+            self.heap.pop();
+            if let Some(text) = self.texts.remove(&id) {
+                due.push(text);
+            }
+        }
+        due
+    }
+
+    pub fn summary(&self, now: Instant) -> String {
+        let mut pending: Vec<_> = self
+            .heap
+            .iter()
+            .filter_map(|Reverse((at, id))| Some((*at, *id, self.texts.get(id)?)))
+            .collect();
+        pending.sort();
+        pending
+            .into_iter()
+            .map(|(at, id, text)| {
+                let secs = at.saturating_duration_since(now).as_secs();
+                format!("#{} in {}s: {}", id, secs, text)
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// Parse a schedule request: `+30s: text` to send after a delay, or
+/// `@<unix seconds>: text` to send at an absolute time. `now` and
+/// `unix_now` are the current time from the state's clock.
+pub fn parse_request(
+    input: &str,
+    now: Instant,
+    unix_now: u64,
+) -> Result<(Instant, String), String> {
+    let (when, text) = input
+        .split_once(": ")
+        .ok_or_else(|| String::from("expected `+30s: text` or `@<unix seconds>: text`"))?;
+    let delay = if let Some(delay) = when.strip_prefix('+') {
+        parse_duration(delay).map_err(|e| e.to_string())?
+    } else if let Some(at) = when.strip_prefix('@') {
+        let at: u64 = at
+            .parse()
+            .map_err(|_| format!("invalid unix time: {}", at))?;
+        Duration::from_secs(at.saturating_sub(unix_now))
+    } else {
+        return Err(format!("invalid schedule time: {}", when));
+    };
+    Ok((now + delay, text.to_string()))
+}
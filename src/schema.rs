@@ -0,0 +1,205 @@
+use std::{fs, io, path::Path};
+
+use serde_json::Value;
+use spider_client::message::DatasetData;
+
+/// What happens to a payload that does not match the schema.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum SchemaMode {
+    /// Store it with a `schema_error` field describing the mismatch.
+    Flag,
+    /// Drop it.
+    Reject,
+}
+
+/// A JSON schema for incoming payloads. Only a subset is understood:
+/// `type`, `enum`, `required`, `properties` and `additionalProperties:
+/// false`, applied recursively through `properties` and `items`.
+#[derive(Clone)]
+pub struct Schema {
+    root: Value,
+}
+
+impl Schema {
+    pub fn load(path: &Path) -> Result<Self, io::Error> {
+        let contents = fs::read_to_string(path)?;
+        let root = serde_json::from_str(&contents)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        Ok(Self { root })
+    }
+
+    /// Check a payload, returning the first mismatch found.
+    pub fn validate(&self, data: &DatasetData) -> Result<(), String> {
+        check(&self.root, &to_json(data)?, "$")
+    }
+}
+
+/// Maps and strings become plain JSON objects and strings so schemas can
+/// describe payloads directly; other values keep their serde form.
+fn to_json(data: &DatasetData) -> Result<Value, String> {
+    match data {
+        DatasetData::Map(map) => map
+            .iter()
+            .map(|(key, value)| Ok((key.clone(), to_json(value)?)))
+            .collect::<Result<_, String>>()
+            .map(Value::Object),
+        DatasetData::String(text) => Ok(Value::String(text.clone())),
+        data => serde_json::to_value(data).map_err(|e| e.to_string()),
+    }
+}
+
+fn check(schema: &Value, value: &Value, at: &str) -> Result<(), String> {
+    if let Some(expected) = schema.get("type").and_then(Value::as_str) {
+        if !has_type(value, expected) {
+            return Err(format!("{}: expected {}", at, expected));
+        }
+    }
+    if let Some(allowed) = schema.get("enum").and_then(Value::as_array) {
+        if !allowed.contains(value) {
+            return Err(format!("{}: not one of the allowed values", at));
+        }
+    }
+    if let Value::Object(map) = value {
+        let required = schema.get("required").and_then(Value::as_array);
+        for key in required.into_iter().flatten().filter_map(Value::as_str) {
+            if !map.contains_key(key) {
+                return Err(format!("{}: missing {:?}", at, key));
+            }
+        }
+        let properties = schema.get("properties").and_then(Value::as_object);
+        for (key, child) in map {
+            match properties.and_then(|properties| properties.get(key)) {
+                Some(child_schema) => check(child_schema, child, &format!("{}.{}", at, key))?,
+                None if schema.get("additionalProperties") == Some(&Value::Bool(false)) => {
+                    return Err(format!("{}: unexpected {:?}", at, key));
+                }
+                None => {}
+            }
+        }
+    }
+    if let (Value::Array(items), Some(item_schema)) = (value, schema.get("items")) {
+        for (idx, item) in items.iter().enumerate() {
+            check(item_schema, item, &format!("{}[{}]", at, idx))?;
+        }
+    }
+    Ok(())
+}
+
+fn has_type(value: &Value, expected: &str) -> bool {
+    match expected {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "number" => value.is_number(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use serde_json::json;
+
+    use super::*;
+
+    fn schema(root: Value) -> Schema {
+        Schema { root }
+    }
+
+    fn payload(fields: &[(&str, &str)]) -> DatasetData {
+        let map: BTreeMap<String, DatasetData> = fields
+            .iter()
+            .map(|&(key, value)| (key.to_string(), DatasetData::String(value.to_string())))
+            .collect();
+        DatasetData::Map(map)
+    }
+
+    fn message_schema() -> Schema {
+        schema(json!({
+            "type": "object",
+            "required": ["text", "level"],
+            "properties": {
+                "text": { "type": "string" },
+                "level": { "enum": ["info", "warn"] },
+                "id": { "type": "string" },
+            },
+            "additionalProperties": false,
+        }))
+    }
+
+    #[test]
+    fn accepts_a_conforming_payload() {
+        let data = payload(&[("text", "hi"), ("level", "info"), ("id", "1")]);
+        assert_eq!(message_schema().validate(&data), Ok(()));
+    }
+
+    #[test]
+    fn rejects_a_missing_required_field() {
+        let data = payload(&[("text", "hi")]);
+        assert_eq!(
+            message_schema().validate(&data),
+            Err(String::from("$: missing \"level\""))
+        );
+    }
+
+    #[test]
+    fn rejects_properties_not_in_the_schema() {
+        let data = payload(&[("text", "hi"), ("level", "info"), ("extra", "x")]);
+        assert_eq!(
+            message_schema().validate(&data),
+            Err(String::from("$: unexpected \"extra\""))
+        );
+    }
+
+    #[test]
+    fn rejects_values_outside_the_enum() {
+        let data = payload(&[("text", "hi"), ("level", "debug")]);
+        assert_eq!(
+            message_schema().validate(&data),
+            Err(String::from("$.level: not one of the allowed values"))
+        );
+    }
+
+    #[test]
+    fn rejects_the_wrong_type() {
+        let data = DatasetData::String(String::from("hi"));
+        assert_eq!(
+            message_schema().validate(&data),
+            Err(String::from("$: expected object"))
+        );
+    }
+
+    /// Payload maps only hold strings and maps here, so nested arrays are
+    /// checked on the JSON form `validate` converts payloads to.
+    #[test]
+    fn checks_nested_items() {
+        let schema = json!({
+            "properties": {
+                "tags": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "required": ["name"],
+                        "properties": { "name": { "type": "string" } },
+                    },
+                },
+            },
+        });
+        let good = json!({ "tags": [{ "name": "a" }, { "name": "b" }] });
+        assert_eq!(check(&schema, &good, "$"), Ok(()));
+        let bad = json!({ "tags": [{ "name": "a" }, { "name": 2 }] });
+        assert_eq!(
+            check(&schema, &bad, "$"),
+            Err(String::from("$.tags[1].name: expected string"))
+        );
+        let missing = json!({ "tags": [{}] });
+        assert_eq!(
+            check(&schema, &missing, "$"),
+            Err(String::from("$.tags[0]: missing \"name\""))
+        );
+    }
+}
@@ -0,0 +1,193 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    ops::Bound,
+};
+
+use spider_client::message::DatasetData;
+
+use crate::messages;
+
+/// Inverted index over the text of the Messages dataset.
+///
+/// Every suffix of every word is a key, so a query term matching part of a
+/// word is a prefix lookup: one range scan of the ordered map instead of a
+/// pass over the whole vocabulary.
+///
+/// Positions are absolute: the first message ever indexed is 0, and
+/// `offset` counts how many have since been trimmed from the front, so
+/// trimming never renumbers the rest of the index.
+#[derive(Default)]
+pub struct SearchIndex {
+    offset: usize,
+    texts: VecDeque<String>,
+    suffixes: BTreeMap<String, BTreeSet<usize>>,
+}
+
+impl SearchIndex {
+    /// Bring the index in line with the current dataset contents.
+    ///
+    /// The dataset only ever loses entries at the front and gains them at
+    /// the back, so only the difference is indexed. The longest run at the
+    /// end of the old contents that starts the new contents is kept; if
+    /// nothing lines up, every old entry is dropped and the new ones
+    /// indexed from scratch.
+    pub fn sync(&mut self, msgs: &[DatasetData]) {
+        let texts: Vec<String> = msgs.iter().map(messages::text).collect();
+        let kept = overlap(&self.texts, &texts);
+        for _ in kept..self.texts.len() {
+            self.pop();
+        }
+        texts
+            .into_iter()
+            .skip(kept)
+            .for_each(|text| self.push(text));
+    }
+
+    /// Indices into the current dataset of messages containing every word
+    /// of the query as a substring of one of their words.
+    pub fn query(&self, query: &str) -> Vec<usize> {
+        let mut result: Option<BTreeSet<usize>> = None;
+        for term in tokenize(query) {
+            let mut matches = BTreeSet::new();
+            let range = self
+                .suffixes
+                .range::<str, _>((Bound::Included(term.as_str()), Bound::Unbounded));
+            for (suffix, positions) in range {
+                if !suffix.starts_with(&term) {
+                    break;
+                }
+                matches.extend(positions);
+            }
+            result = Some(match result {
+                Some(result) => result.intersection(&matches).copied().collect(),
+                None => matches,
+            });
+        }
+        result
+            .unwrap_or_default()
+            .into_iter()
+            .map(|pos| pos - self.offset)
+            .collect()
+    }
+
+    fn push(&mut self, text: String) {
+        let pos = self.offset + self.texts.len();
+        for suffix in suffixes(&text) {
+            self.suffixes.entry(suffix).or_default().insert(pos);
+        }
+        self.texts.push_back(text);
+    }
+
+    fn pop(&mut self) {
+        let Some(text) = self.texts.pop_front() else {
+            return;
+        };
+        for suffix in suffixes(&text) {
+            if let Some(positions) = self.suffixes.get_mut(&suffix) {
+                positions.remove(&self.offset);
+                if positions.is_empty() {
+                    self.suffixes.remove(&suffix);
+                }
+            }
+        }
+        self.offset += 1;
+    }
+}
+
+/// Length of the longest run at the end of `old` that `new` starts with,
+/// found in linear time with the Knuth-Morris-Pratt failure function.
+fn overlap(old: &VecDeque<String>, new: &[String]) -> usize {
+    if new.is_empty() {
+        return 0;
+    }
+    let mut failure = vec![0; new.len()];
+    let mut k = 0;
+    for i in 1..new.len() {
+        while k > 0 && new[i] != new[k] {
+            k = failure[k - 1];
+        }
+        if new[i] == new[k] {
+            k += 1;
+        }
+        failure[i] = k;
+    }
+    let mut matched = 0;
+    for text in old {
+        if matched == new.len() {
+            matched = failure[matched - 1];
+        }
+        while matched > 0 && *text != new[matched] {
+            matched = failure[matched - 1];
+        }
+        if *text == new[matched] {
+            matched += 1;
+        }
+    }
+    matched
+}
+
+fn tokenize(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+}
+
+/// Every suffix of every word of `text`, without repeats.
+fn suffixes(text: &str) -> BTreeSet<String> {
+    tokenize(text)
+        .flat_map(|word| {
+            word.char_indices()
+                .map(|(i, _)| word[i..].to_string())
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::*;
+
+    fn msgs(range: std::ops::Range<usize>) -> Vec<DatasetData> {
+        range
+            .map(|i| DatasetData::String(format!("message number{} from sender{}", i, i % 7)))
+            .collect()
+    }
+
+    #[test]
+    fn finds_substrings_of_words() {
+        let mut index = SearchIndex::default();
+        index.sync(&msgs(0..20));
+        assert_eq!(index.query("umber13"), vec![13]);
+        assert_eq!(index.query("sender3 number1"), vec![10, 17]);
+        assert!(index.query("missing").is_empty());
+    }
+
+    #[test]
+    fn stays_consistent_when_the_oldest_are_trimmed() {
+        let mut index = SearchIndex::default();
+        index.sync(&msgs(0..20));
+        index.sync(&msgs(5..25));
+        assert_eq!(index.query("number3"), Vec::<usize>::new());
+        assert_eq!(index.query("number13"), vec![8]);
+        assert_eq!(index.query("number24"), vec![19]);
+    }
+
+    #[test]
+    fn thousands_of_messages_stay_responsive() {
+        let mut index = SearchIndex::default();
+        let start = Instant::now();
+        for end in (500..=5000usize).step_by(500) {
+            index.sync(&msgs(end.saturating_sub(4000)..end));
+        }
+        let queries = Instant::now();
+        for i in 0..1000 {
+            assert_eq!(index.query(&format!("number{}", 4000 + i % 10)).len(), 1);
+        }
+        // generous bounds so slow CI machines pass; the old linear scans
+        // took far longer than this at this size
+        assert!(queries.elapsed() < Duration::from_secs(2));
+        assert!(start.elapsed() < Duration::from_secs(10));
+    }
+}
@@ -0,0 +1,234 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use spider_client::{message::DatasetData, Relation};
+
+use crate::messages;
+
+/// How many finished sends are kept for display.
+const HISTORY: usize = 10;
+
+pub enum SendStatus {
+    Sending,
+    Sent,
+    Failed(String),
+}
+
+pub struct OutboundSend {
+    pub id: u64,
+    pub name: String,
+    pub text: String,
+    pub recps: Vec<Relation>,
+    pub data: DatasetData,
+    /// Id carried in the payload, which receivers echo back in receipts.
+    pub msg_id: Option<String>,
+    /// Recipients that have sent a receipt.
+    pub acked: Vec<Relation>,
+    /// 0 for the first try, counting up with each automatic retry.
+    pub attempt: u32,
+    pub sent_at: Instant,
+    pub status: SendStatus,
+}
+
+impl OutboundSend {
+    /// Recipients that have not sent a receipt yet.
+    pub fn unacked(&self) -> Vec<Relation> {
+        self.recps
+            .iter()
+            .filter(|recp| !self.acked.contains(recp))
+            .cloned()
+            .collect()
+    }
+}
+
+/// A failed send waiting to be tried again.
+pub struct Retry {
+    pub due: Instant,
+    pub name: String,
+    pub recps: Vec<Relation>,
+    pub data: DatasetData,
+    pub attempt: u32,
+}
+
+/// Tracks events handed to the router until they are confirmed or fail.
+///
+/// Each tracked send is one `SendEvent` on the wire; a test event to several
+/// recipients is tracked as one send per recipient, sharing its `msg_id`.
+/// The router does not acknowledge `SendEvent`, so a send that produces no
+/// error within the ack window is treated as delivered. Errors are matched
+/// to the oldest send still in flight, since the router answers in order.
+/// Receivers answer messages that carry an id with a receipt, and a send
+/// counts as delivered once every recipient has answered.
+///
+/// Failed sends are retried up to `max_retries` times, waiting `backoff`
+/// before the first retry and doubling the wait each time after.
+pub struct SendTracker {
+    next_id: u64,
+    window: Duration,
+    max_retries: u32,
+    backoff: Duration,
+    sends: VecDeque<OutboundSend>,
+    retries: Vec<Retry>,
+}
+
+impl SendTracker {
+    pub fn new(window: Duration, max_retries: u32, backoff: Duration) -> Self {
+        Self {
+            next_id: 0,
+            window,
+            max_retries,
+            backoff,
+            sends: VecDeque::new(),
+            retries: vec![],
+        }
+    }
+
+    pub fn start(
+        &mut self,
+        name: String,
+        recps: Vec<Relation>,
+        data: DatasetData,
+        attempt: u32,
+        now: Instant,
+    ) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.sends.push_back(OutboundSend {
+            id,
+            name,
+            text: messages::text(&data),
+            msg_id: messages::id(&data).map(str::to_string),
+            acked: vec![],
+            recps,
+            data,
+            attempt,
+            sent_at: now,
+            status: SendStatus::Sending,
+        });
+        self.trim();
+        id
+    }
+
+    /// How many sends are still waiting for their outcome.
+    pub fn in_flight(&self) -> usize {
+        self.sends
+            .iter()
+            .filter(|send| matches!(send.status, SendStatus::Sending))
+            .count()
+    }
+
+    /// The sends making up the most recent logical send: the last send and
+    /// those before it carrying the same payload id.
+    pub fn last_group(&self) -> Vec<&OutboundSend> {
+        let Some(last) = self.sends.back() else {
+            return vec![];
+        };
+        let Some(msg_id) = &last.msg_id else {
+            return vec![last];
+        };
+        let mut group: Vec<_> = self
+            .sends
+            .iter()
+            .rev()
+            .take_while(|send| send.msg_id.as_ref() == Some(msg_id))
+            .collect();
+        group.reverse();
+        group
+    }
+
+    /// Mark the oldest in-flight send as failed, returning it if there was one.
+    /// A retry is queued if the send has attempts left.
+    pub fn fail_oldest(&mut self, reason: String, now: Instant) -> Option<&OutboundSend> {
+        let send = self
+            .sends
+            .iter_mut()
+            .find(|send| matches!(send.status, SendStatus::Sending))?;
+        send.status = SendStatus::Failed(reason);
+        if send.attempt < self.max_retries {
+            self.retries.push(Retry {
+                due: now + self.backoff * 2u32.saturating_pow(send.attempt),
+                name: send.name.clone(),
+                recps: send.recps.clone(),
+                data: send.data.clone(),
+                attempt: send.attempt + 1,
+            });
+        }
+        Some(send)
+    }
+
+    /// Record a receipt from `from` for the message with `msg_id`.
+    /// Returns whether it matched a tracked send.
+    pub fn record_receipt(&mut self, msg_id: &str, from: &Relation) -> bool {
+        let Some(send) = self
+            .sends
+            .iter_mut()
+            .rev()
+            .find(|send| send.msg_id.as_deref() == Some(msg_id) && send.recps.contains(from))
+        else {
+            return false;
+        };
+        if !send.acked.contains(from) {
+            send.acked.push(from.clone());
+        }
+        true
+    }
+
+    /// Remove and return the retries that are due.
+    pub fn pop_due_retries(&mut self, now: Instant) -> Vec<Retry> {
+        let (due, waiting) = std::mem::take(&mut self.retries)
+            .into_iter()
+            .partition(|retry| retry.due <= now);
+        self.retries = waiting;
+        due
+    }
+
+    /// Confirm every in-flight send older than the ack window.
+    /// Returns whether any status changed.
+    pub fn confirm_expired(&mut self, now: Instant) -> bool {
+        let mut changed = false;
+        for send in self.sends.iter_mut() {
+            if matches!(send.status, SendStatus::Sending)
+                && now.duration_since(send.sent_at) >= self.window
+            {
+                send.status = SendStatus::Sent;
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    pub fn summary(&self) -> String {
+        let mut lines = vec![];
+        for send in self.sends.iter().rev() {
+            let delivered = send.recps.iter().all(|recp| send.acked.contains(recp));
+            let status = match &send.status {
+                SendStatus::Failed(reason) => format!("\u{2717} failed ({})", reason),
+                _ if delivered && !send.recps.is_empty() => {
+                    String::from("\u{2713}\u{2713} delivered")
+                }
+                SendStatus::Sending => String::from("\u{2026} sending"),
+                SendStatus::Sent => String::from("\u{2713} sent"),
+            };
+            let attempt = match send.attempt {
+                0 => String::new(),
+                n => format!(" (retry {})", n),
+            };
+            lines.push(format!("#{} {}{}: {}", send.id, status, attempt, send.text));
+        }
+        lines.join("\n")
+    }
+
+    fn trim(&mut self) {
+        while self.sends.len() > HISTORY {
+            // never drop a send whose outcome is still pending
+            match self.sends.front() {
+                Some(send) if !matches!(send.status, SendStatus::Sending) => {
+                    self.sends.pop_front();
+                }
+                _ => break,
+            }
+        }
+    }
+}
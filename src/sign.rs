@@ -0,0 +1,198 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::Path,
+};
+
+use ed25519_dalek::{Signature, Signer as _, SigningKey, Verifier as _, VerifyingKey};
+use spider_client::message::DatasetData;
+
+use crate::encoding::Encoding;
+
+/// Identity property holding the base-64 public key that verifies a
+/// client's signatures.
+pub const KEY_PROPERTY: &str = "sign_key";
+
+/// Payload field holding the base-64 signature.
+const SIGNATURE_FIELD: &str = "signature";
+
+/// Signs outgoing payload maps with an ed25519 key kept beside the spider
+/// keyfile.
+///
+/// `spider_client` does not expose the client's own private key, so this
+/// key is separate; its public half is published in the directory under
+/// [`KEY_PROPERTY`] for receivers to verify against.
+pub struct Signer {
+    key: SigningKey,
+}
+
+impl Signer {
+    /// Load the key at `path`, creating it if it does not exist yet.
+    pub fn load_or_create(path: &Path) -> Result<Self, io::Error> {
+        let seed = match fs::read(path) {
+            Ok(bytes) => bytes.try_into().map_err(|_| {
+                io::Error::new(io::ErrorKind::InvalidData, "signing key must be 32 bytes")
+            })?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                let mut seed = [0; 32];
+                getrandom::getrandom(&mut seed)
+                    .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+                write_private(path, &seed)?;
+                seed
+            }
+            Err(e) => return Err(e),
+        };
+        Ok(Self {
+            key: SigningKey::from_bytes(&seed),
+        })
+    }
+
+    pub fn public_key(&self) -> String {
+        encode(&self.key.verifying_key().to_bytes())
+    }
+
+    /// Add a signature over the rest of a payload map. Other payloads are
+    /// returned unchanged.
+    pub fn sign(&self, data: DatasetData) -> DatasetData {
+        match data {
+            DatasetData::Map(mut map) => {
+                map.remove(SIGNATURE_FIELD);
+                let signature = self.key.sign(&signed_bytes(&DatasetData::Map(map.clone())));
+                map.insert(
+                    String::from(SIGNATURE_FIELD),
+                    DatasetData::String(encode(&signature.to_bytes())),
+                );
+                DatasetData::Map(map)
+            }
+            data => data,
+        }
+    }
+}
+
+/// Check a received payload's signature against the sender's published
+/// key, returning why it could not be verified.
+pub fn verify(data: &DatasetData, public_key: Option<&str>) -> Result<(), &'static str> {
+    let DatasetData::Map(map) = data else {
+        return Err("unsigned");
+    };
+    let Some(DatasetData::String(signature)) = map.get(SIGNATURE_FIELD) else {
+        return Err("unsigned");
+    };
+    let public_key = public_key.ok_or("sender has no published key")?;
+    let public_key: [u8; 32] = decode(public_key)
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or("sender's published key is malformed")?;
+    let public_key =
+        VerifyingKey::from_bytes(&public_key).map_err(|_| "sender's published key is malformed")?;
+    let signature = decode(signature)
+        .and_then(|bytes| Signature::from_slice(&bytes).ok())
+        .ok_or("signature is malformed")?;
+    let mut unsigned = map.clone();
+    unsigned.remove(SIGNATURE_FIELD);
+    public_key
+        .verify(&signed_bytes(&DatasetData::Map(unsigned)), &signature)
+        .map_err(|_| "signature does not match")
+}
+
+/// The bytes a signature covers. Map keys are ordered, so the JSON form is
+/// the same on both ends.
+fn signed_bytes(data: &DatasetData) -> Vec<u8> {
+    serde_json::to_vec(data).unwrap_or_default()
+}
+
+/// Write a new file readable only by its owner where the platform allows.
+fn write_private(path: &Path, bytes: &[u8]) -> Result<(), io::Error> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)?.write_all(bytes)
+}
+
+fn encode(bytes: &[u8]) -> String {
+    Encoding::Base64.encode(bytes).unwrap_or_default()
+}
+
+fn decode(text: &str) -> Option<Vec<u8>> {
+    Encoding::Base64.decode(text).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+    fn signer(seed: u8) -> Signer {
+        Signer {
+            key: SigningKey::from_bytes(&[seed; 32]),
+        }
+    }
+
+    fn payload(text: &str) -> DatasetData {
+        let mut map = BTreeMap::new();
+        map.insert(String::from("text"), DatasetData::String(text.to_string()));
+        map.insert(String::from("id"), DatasetData::String(String::from("1-0")));
+        DatasetData::Map(map)
+    }
+
+    fn set(data: DatasetData, key: &str, value: &str) -> DatasetData {
+        let DatasetData::Map(mut map) = data else {
+            panic!("payload is not a map");
+        };
+        map.insert(key.to_string(), DatasetData::String(value.to_string()));
+        DatasetData::Map(map)
+    }
+
+    #[test]
+    fn signed_payload_verifies() {
+        let signer = signer(1);
+        let data = signer.sign(payload("hello"));
+        assert_eq!(verify(&data, Some(&signer.public_key())), Ok(()));
+    }
+
+    #[test]
+    fn modified_field_does_not_match() {
+        let signer = signer(1);
+        let data = set(signer.sign(payload("hello")), "text", "goodbye");
+        assert_eq!(
+            verify(&data, Some(&signer.public_key())),
+            Err("signature does not match")
+        );
+    }
+
+    #[test]
+    fn wrong_key_does_not_match() {
+        let data = signer(1).sign(payload("hello"));
+        assert_eq!(
+            verify(&data, Some(&signer(2).public_key())),
+            Err("signature does not match")
+        );
+    }
+
+    #[test]
+    fn unsigned_map_is_reported() {
+        let key = signer(1).public_key();
+        assert_eq!(verify(&payload("hello"), Some(&key)), Err("unsigned"));
+    }
+
+    #[test]
+    fn malformed_signature_or_key_is_rejected() {
+        let signer = signer(1);
+        let key = signer.public_key();
+        let data = set(signer.sign(payload("hello")), SIGNATURE_FIELD, "AAAA");
+        assert_eq!(verify(&data, Some(&key)), Err("signature is malformed"));
+        let data = set(payload("hello"), SIGNATURE_FIELD, "not base64!");
+        assert_eq!(verify(&data, Some(&key)), Err("signature is malformed"));
+
+        let data = signer.sign(payload("hello"));
+        assert_eq!(
+            verify(&data, Some("AAAA")),
+            Err("sender's published key is malformed")
+        );
+        assert_eq!(verify(&data, None), Err("sender has no published key"));
+    }
+}
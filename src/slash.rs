@@ -0,0 +1,53 @@
+use std::time::Duration;
+
+use crate::config;
+
+/// A command typed into the Send field with a leading slash.
+pub enum SlashCommand {
+    /// Send to a single recipient by its index in the Recp list.
+    To(usize, String),
+    /// Send to every recipient, regardless of the send mode.
+    All(String),
+    /// Send ahead of everything queued by `--send-rate`.
+    Urgent(String),
+    /// Send to every active recipient carrying a tag.
+    Tag(String, String),
+    /// Send a message receivers delete once it is this old.
+    Ttl(Duration, String),
+    /// Delete every message from the history.
+    Clear,
+}
+
+/// Parse Send field input. Returns `None` for plain text, which is sent as
+/// a normal message.
+pub fn parse(input: &str) -> Option<Result<SlashCommand, String>> {
+    let input = input.strip_prefix('/')?;
+    let (name, rest) = input.split_once(' ').unwrap_or((input, ""));
+    let command = match name {
+        "to" => match rest.split_once(' ') {
+            Some((idx, text)) => match idx.parse() {
+                Ok(idx) => Ok(SlashCommand::To(idx, text.to_string())),
+                Err(_) => Err(format!("/to expects a recipient index, got {:?}", idx)),
+            },
+            None => Err(String::from("usage: /to <idx> <text>")),
+        },
+        "all" if !rest.is_empty() => Ok(SlashCommand::All(rest.to_string())),
+        "all" => Err(String::from("usage: /all <text>")),
+        "urgent" if !rest.is_empty() => Ok(SlashCommand::Urgent(rest.to_string())),
+        "urgent" => Err(String::from("usage: /urgent <text>")),
+        "ttl" => match rest.split_once(' ') {
+            Some((ttl, text)) => match config::parse_duration(ttl) {
+                Ok(ttl) => Ok(SlashCommand::Ttl(ttl, text.to_string())),
+                Err(e) => Err(e.to_string()),
+            },
+            None => Err(String::from("usage: /ttl <30s> <text>")),
+        },
+        "tag" => match rest.split_once(' ') {
+            Some((tag, text)) => Ok(SlashCommand::Tag(tag.to_string(), text.to_string())),
+            None => Err(String::from("usage: /tag <tag> <text>")),
+        },
+        "clear" => Ok(SlashCommand::Clear),
+        _ => Err(format!("unknown command /{}", name)),
+    };
+    Some(command)
+}
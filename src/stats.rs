@@ -0,0 +1,134 @@
+use std::{
+    collections::{BTreeSet, HashMap, VecDeque},
+    time::Instant,
+};
+
+use spider_client::Relation;
+
+use crate::relation;
+
+/// How many recent reachability checks are remembered per recipient.
+const PING_HISTORY: usize = 5;
+
+#[derive(Default)]
+pub struct RecpStats {
+    pub sent: u64,
+    pub failed: u64,
+    pub last_sent: Option<Instant>,
+    /// Muted recipients are kept in the list but skipped by sends.
+    pub muted: bool,
+    /// Whether each recent reachability check was answered, oldest first.
+    pub recent_pings: VecDeque<bool>,
+    /// Labels for picking out groups of recipients, such as with `/tag`.
+    /// Page text has no styling, so rows show them as bracketed `[#tag]`
+    /// labels; only the terminal log colors them.
+    pub tags: BTreeSet<String>,
+}
+
+/// Per-recipient send tallies.
+#[derive(Default)]
+pub struct RecpStatsTable {
+    stats: HashMap<Relation, RecpStats>,
+}
+
+impl RecpStatsTable {
+    pub fn record_sent(&mut self, recps: &[Relation], now: Instant) {
+        for recp in recps {
+            let stats = self.stats.entry(recp.clone()).or_default();
+            stats.sent += 1;
+            stats.last_sent = Some(now);
+        }
+    }
+
+    pub fn record_failed(&mut self, recps: &[Relation]) {
+        for recp in recps {
+            self.stats.entry(recp.clone()).or_default().failed += 1;
+        }
+    }
+
+    pub fn record_ping(&mut self, recp: &Relation, answered: bool) {
+        let pings = &mut self.stats.entry(recp.clone()).or_default().recent_pings;
+        if pings.len() == PING_HISTORY {
+            pings.pop_front();
+        }
+        pings.push_back(answered);
+    }
+
+    /// Flip whether `recp` is muted, returning the new state.
+    pub fn toggle_muted(&mut self, recp: &Relation) -> bool {
+        let stats = self.stats.entry(recp.clone()).or_default();
+        stats.muted = !stats.muted;
+        stats.muted
+    }
+
+    /// Add `tag` to `recp`, or remove it if present, returning whether the
+    /// recipient now has it.
+    pub fn toggle_tag(&mut self, recp: &Relation, tag: &str) -> bool {
+        let tags = &mut self.stats.entry(recp.clone()).or_default().tags;
+        if tags.remove(tag) {
+            false
+        } else {
+            tags.insert(tag.to_string());
+            true
+        }
+    }
+
+    pub fn has_tag(&self, recp: &Relation, tag: &str) -> bool {
+        self.stats
+            .get(recp)
+            .is_some_and(|stats| stats.tags.contains(tag))
+    }
+
+    pub fn is_muted(&self, recp: &Relation) -> bool {
+        self.stats.get(recp).is_some_and(|stats| stats.muted)
+    }
+
+    /// One line per recipient, in the given order.
+    pub fn render(&self, recps: &[Relation], now: Instant) -> String {
+        let mut lines = vec![];
+        for recp in recps {
+            let line = match self.stats.get(recp) {
+                Some(stats) => {
+                    let last = match stats.last_sent {
+                        Some(at) => format!("{}s ago", now.duration_since(at).as_secs()),
+                        None => String::from("never"),
+                    };
+                    format!(
+                        "{}: {} sent, {} failed, last {}",
+                        relation::display(recp),
+                        stats.sent,
+                        stats.failed,
+                        last
+                    )
+                }
+                None => format!("{}: nothing sent", relation::display(recp)),
+            };
+            let mut line = line;
+            if let Some(stats) = self.stats.get(recp).filter(|s| !s.recent_pings.is_empty()) {
+                let answered = stats.recent_pings.iter().filter(|&&ok| ok).count();
+                let state = match stats.recent_pings.back() {
+                    Some(true) => "reachable",
+                    _ => "unreachable",
+                };
+                line = format!(
+                    "{} [{}, {}/{} checks answered]",
+                    line,
+                    state,
+                    answered,
+                    stats.recent_pings.len()
+                );
+            }
+            if let Some(stats) = self.stats.get(recp) {
+                for tag in &stats.tags {
+                    line = format!("{} [#{}]", line, tag);
+                }
+            }
+            if self.is_muted(recp) {
+                lines.push(format!("{} [muted]", line));
+            } else {
+                lines.push(line);
+            }
+        }
+        lines.join("\n")
+    }
+}
@@ -0,0 +1,196 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use spider_client::{
+    message::{Message, RouterMessage},
+    ClientChannel, ClientResponse, Relation,
+};
+
+use crate::{config::Config, messages, traffic::Traffic};
+
+/// The load applied by the `stress` subcommand.
+#[derive(Clone)]
+pub struct StressProfile {
+    /// How long to keep sending.
+    pub duration: Duration,
+    /// Messages per second at the start, ramping linearly to `end_rate`.
+    pub start_rate: f64,
+    pub end_rate: f64,
+    /// Payload text sizes in bytes, spread across this range.
+    pub min_size: u64,
+    pub max_size: u64,
+    /// Send to at most this many of the recipients.
+    pub recps: Option<usize>,
+}
+
+impl Default for StressProfile {
+    fn default() -> Self {
+        Self {
+            duration: Duration::from_secs(30),
+            start_rate: 1.0,
+            end_rate: 10.0,
+            min_size: 16,
+            max_size: 1 << 10,
+            recps: None,
+        }
+    }
+}
+
+impl StressProfile {
+    /// The send rate `elapsed` into the run.
+    fn rate_at(&self, elapsed: Duration) -> f64 {
+        let progress = (elapsed.as_secs_f64() / self.duration.as_secs_f64()).min(1.0);
+        let rate = self.start_rate + (self.end_rate - self.start_rate) * progress;
+        rate.max(0.01)
+    }
+
+    /// The payload size of message `seq`, stepping through the range so
+    /// every size is covered without a random source.
+    fn size_of(&self, seq: u64) -> usize {
+        let span = self.max_size.saturating_sub(self.min_size) + 1;
+        (self.min_size + seq.wrapping_mul(7919) % span) as usize
+    }
+}
+
+#[derive(Default)]
+struct Stats {
+    sent: u64,
+    bytes: u64,
+    expected_receipts: u64,
+    latencies: Vec<Duration>,
+    errors: u64,
+}
+
+/// Send to the recipient file and CC peers following `config.stress`, then
+/// log a report. Latency is measured from send to the recipient's receipt,
+/// so only peers that send receipts contribute to it. Returns whether the
+/// run completed without errors.
+pub async fn run(client: &mut ClientChannel, config: &Config, traffic: &mut Traffic) -> bool {
+    let profile = &config.stress;
+    let mut recps: Vec<Relation> = config.file_recps.clone();
+    for relation in &config.cc {
+        if !recps.contains(relation) {
+            recps.push(relation.clone());
+        }
+    }
+    if let Some(limit) = profile.recps {
+        recps.truncate(limit);
+    }
+    if recps.is_empty() {
+        log!("stress needs recipients from --recp-file or --cc");
+        return false;
+    }
+    log!(
+        "Stressing {} recipients for {}s at {} to {} msgs/s",
+        recps.len(),
+        profile.duration.as_secs(),
+        profile.start_rate,
+        profile.end_rate
+    );
+
+    let mut stats = Stats::default();
+    let mut sent_at: HashMap<String, Instant> = HashMap::new();
+    let start = Instant::now();
+    let end = start + profile.duration;
+    // after the last send, wait one ack window for stragglers
+    let drain_until = end + config.ack_window;
+    let mut next_send = start;
+    loop {
+        let now = Instant::now();
+        if now >= drain_until {
+            break;
+        }
+        let wake = if next_send < end {
+            next_send
+        } else {
+            drain_until
+        };
+        tokio::select! {
+            _ = tokio::time::sleep_until(wake.into()) => {
+                if next_send >= end {
+                    continue;
+                }
+                let id = format!("stress-{}", stats.sent);
+                let text = "x".repeat(profile.size_of(stats.sent));
+                stats.bytes += text.len() as u64;
+                stats.sent += 1;
+                stats.expected_receipts += recps.len() as u64;
+                sent_at.insert(id.clone(), Instant::now());
+                let msg = Message::Router(RouterMessage::SendEvent(
+                    String::from("test_event"),
+                    recps.clone(),
+                    messages::outbound(text, id),
+                ));
+                traffic.record_sent(&msg);
+                client.send(msg).await;
+                let interval = 1.0 / profile.rate_at(next_send.duration_since(start));
+                next_send += Duration::from_secs_f64(interval);
+            }
+            response = client.recv() => {
+                if let Some(ClientResponse::Message(msg)) = &response {
+                    traffic.record_received(msg);
+                }
+                match response {
+                    Some(ClientResponse::Message(Message::Router(RouterMessage::Event(
+                        name,
+                        _,
+                        data,
+                    )))) if name == "receipt" => {
+                        let sent = messages::id(&data).and_then(|id| sent_at.get(id));
+                        if let Some(sent) = sent {
+                            stats.latencies.push(sent.elapsed());
+                        }
+                    }
+                    Some(ClientResponse::Message(Message::Error(e))) => {
+                        log!(Error; "Router error: {:?}", e);
+                        stats.errors += 1;
+                    }
+                    Some(ClientResponse::Denied(_)) | None => {
+                        log!(Error; "Connection closed during the stress run");
+                        stats.errors += 1;
+                        break;
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    report(&stats, start.elapsed().min(profile.duration));
+    stats.errors == 0
+}
+
+fn report(stats: &Stats, elapsed: Duration) {
+    let secs = elapsed.as_secs_f64().max(f64::EPSILON);
+    log!(
+        "Sent {} messages ({} bytes) in {:.1}s: {:.1} msgs/s, {:.0} bytes/s",
+        stats.sent,
+        stats.bytes,
+        secs,
+        stats.sent as f64 / secs,
+        stats.bytes as f64 / secs
+    );
+    log!(
+        "Receipts: {} of {} expected, errors: {}",
+        stats.latencies.len(),
+        stats.expected_receipts,
+        stats.errors
+    );
+    let mut latencies = stats.latencies.clone();
+    if latencies.is_empty() {
+        log!("Latency: no receipts received");
+        return;
+    }
+    latencies.sort();
+    let total: Duration = latencies.iter().sum();
+    let p95 = latencies[(latencies.len() * 95 / 100).min(latencies.len() - 1)];
+    log!(
+        "Latency: min {:?}, avg {:?}, p95 {:?}, max {:?}",
+        latencies[0],
+        total / latencies.len() as u32,
+        p95,
+        latencies[latencies.len() - 1]
+    );
+}
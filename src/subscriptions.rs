@@ -0,0 +1,151 @@
+use std::{fs, io, path::Path};
+
+use serde_json::{json, Value};
+use spider_client::{
+    message::{DatasetMessage, DatasetPath, Message, RouterMessage},
+    ClientChannel,
+};
+
+use crate::{msgs_dataset, recp_dataset, traffic::Traffic, EVENTS};
+
+/// Everything this router has asked the router to send it.
+pub struct Subscriptions {
+    pub datasets: Vec<DatasetPath>,
+    pub events: Vec<String>,
+    /// Event name patterns such as `test_*`, matched by prefix.
+    pub patterns: Vec<String>,
+    pub directory: bool,
+    /// The datasets the Recp and Messages views show, kept with the rest so
+    /// a restored set leaves the page bound to datasets it subscribes to.
+    pub recp_path: DatasetPath,
+    pub msgs_path: DatasetPath,
+}
+
+impl Default for Subscriptions {
+    fn default() -> Self {
+        Self {
+            datasets: vec![recp_dataset(), msgs_dataset()],
+            events: EVENTS.iter().map(|event| event.to_string()).collect(),
+            patterns: vec![],
+            directory: true,
+            recp_path: recp_dataset(),
+            msgs_path: msgs_dataset(),
+        }
+    }
+}
+
+impl Subscriptions {
+    /// Issue every subscription, such as at startup or to recover ones the
+    /// router has dropped.
+    pub async fn send_all(&self, client: &mut ClientChannel, traffic: &mut Traffic) {
+        for path in &self.datasets {
+            let msg = Message::Dataset(DatasetMessage::Subscribe { path: path.clone() });
+            traffic.record_sent(&msg);
+            client.send(msg).await;
+        }
+        if self.directory {
+            let msg = Message::Router(RouterMessage::SubscribeDir);
+            traffic.record_sent(&msg);
+            client.send(msg).await;
+        }
+        for event in self.events.iter().chain(&self.patterns) {
+            let msg = Message::Router(RouterMessage::Subscribe(event.clone()));
+            traffic.record_sent(&msg);
+            client.send(msg).await;
+        }
+    }
+
+    /// Add a pattern subscription. The pattern is passed to the router as-is
+    /// in case it understands wildcards, and is also matched locally.
+    pub async fn add_pattern(
+        &mut self,
+        client: &mut ClientChannel,
+        traffic: &mut Traffic,
+        pattern: String,
+    ) {
+        let msg = Message::Router(RouterMessage::Subscribe(pattern.clone()));
+        traffic.record_sent(&msg);
+        client.send(msg).await;
+        self.patterns.push(pattern);
+    }
+
+    /// Whether an event name matches one of the pattern subscriptions.
+    pub fn matches_pattern(&self, name: &str) -> bool {
+        self.patterns
+            .iter()
+            .any(|pattern| match pattern.strip_suffix('*') {
+                Some(prefix) => name.starts_with(prefix),
+                None => name == pattern,
+            })
+    }
+
+    /// Read a set written by [`save`](Self::save), if there is one.
+    pub fn load(path: &Path) -> Option<Self> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return None,
+            Err(e) => {
+                log!(Error; "Failed to read {}: {}", path.display(), e);
+                return None;
+            }
+        };
+        let parsed = serde_json::from_str::<Value>(&contents)
+            .ok()
+            .and_then(|value| {
+                Some(Self {
+                    datasets: serde_json::from_value(value.get("datasets")?.clone()).ok()?,
+                    events: serde_json::from_value(value.get("events")?.clone()).ok()?,
+                    patterns: serde_json::from_value(value.get("patterns")?.clone()).ok()?,
+                    directory: value.get("directory")?.as_bool()?,
+                    // files from before the paths were saved use the defaults
+                    recp_path: value
+                        .get("recp_path")
+                        .and_then(|path| serde_json::from_value(path.clone()).ok())
+                        .unwrap_or_else(recp_dataset),
+                    msgs_path: value
+                        .get("msgs_path")
+                        .and_then(|path| serde_json::from_value(path.clone()).ok())
+                        .unwrap_or_else(msgs_dataset),
+                })
+            });
+        if parsed.is_none() {
+            log!(Error; "Ignoring malformed subscriptions in {}", path.display());
+        }
+        parsed
+    }
+
+    pub fn save(&self, path: &Path) {
+        let value = json!({
+            "datasets": self.datasets,
+            "events": self.events,
+            "patterns": self.patterns,
+            "directory": self.directory,
+            "recp_path": self.recp_path,
+            "msgs_path": self.msgs_path,
+        });
+        let result = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|()| fs::write(path, value.to_string()));
+        if let Err(e) = result {
+            log!(Error; "Failed to save subscriptions to {}: {}", path.display(), e);
+        }
+    }
+
+    pub fn render(&self) -> String {
+        let mut lines = vec![];
+        for path in &self.datasets {
+            lines.push(format!("dataset {:?}", path));
+        }
+        for event in &self.events {
+            lines.push(format!("event {}", event));
+        }
+        for pattern in &self.patterns {
+            lines.push(format!("pattern {}", pattern));
+        }
+        if self.directory {
+            lines.push(String::from("directory"));
+        }
+        lines.join("\n")
+    }
+}
@@ -0,0 +1,26 @@
+use spider_client::message::{UiElementContent, UiElementContentPart};
+
+/// Parse a display template into element content.
+///
+/// Text inside braces, such as `{text}`, becomes a reference to that field
+/// of the row's dataset entry; everything else is shown literally. An
+/// unterminated brace is kept as literal text.
+pub fn parse(template: &str) -> UiElementContent {
+    let mut content = UiElementContent::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let Some(len) = rest[start..].find('}') else {
+            break;
+        };
+        if start > 0 {
+            content.add_part(UiElementContentPart::Text(rest[..start].to_string()));
+        }
+        let field = &rest[start + 1..start + len];
+        content.add_part(UiElementContentPart::Data(vec![field.to_string()]));
+        rest = &rest[start + len + 1..];
+    }
+    if !rest.is_empty() {
+        content.add_part(UiElementContentPart::Text(rest.to_string()));
+    }
+    content
+}
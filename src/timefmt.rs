@@ -0,0 +1,55 @@
+use chrono::{
+    format::{Item, StrftimeItems},
+    Local, SecondsFormat, TimeZone, Utc,
+};
+
+/// How the `timestamp` of a Messages entry is written.
+#[derive(Clone, PartialEq, Eq)]
+pub enum TimeFormat {
+    /// Whole seconds since the epoch.
+    EpochSecs,
+    EpochMillis,
+    Rfc3339,
+    /// A strftime-style pattern in local time, e.g. `%H:%M:%S`.
+    Custom(String),
+}
+
+impl TimeFormat {
+    /// Parse `secs`, `millis`, `rfc3339` or a strftime pattern.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        match text {
+            "secs" => Ok(TimeFormat::EpochSecs),
+            "millis" => Ok(TimeFormat::EpochMillis),
+            "rfc3339" => Ok(TimeFormat::Rfc3339),
+            pattern => {
+                if StrftimeItems::new(pattern).any(|item| matches!(item, Item::Error)) {
+                    return Err(format!("invalid time format: {}", pattern));
+                }
+                Ok(TimeFormat::Custom(pattern.to_string()))
+            }
+        }
+    }
+
+    /// The current time in this format.
+    pub fn now(&self) -> String {
+        match self {
+            TimeFormat::EpochSecs => Utc::now().timestamp().to_string(),
+            TimeFormat::EpochMillis => Utc::now().timestamp_millis().to_string(),
+            TimeFormat::Rfc3339 => Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+            TimeFormat::Custom(pattern) => Local::now().format(pattern).to_string(),
+        }
+    }
+
+    /// `secs`, in seconds since the epoch, in this format.
+    pub fn format(&self, secs: u64) -> String {
+        let Some(utc) = Utc.timestamp_opt(secs as i64, 0).single() else {
+            return secs.to_string();
+        };
+        match self {
+            TimeFormat::EpochSecs => secs.to_string(),
+            TimeFormat::EpochMillis => utc.timestamp_millis().to_string(),
+            TimeFormat::Rfc3339 => utc.to_rfc3339_opts(SecondsFormat::Secs, true),
+            TimeFormat::Custom(pattern) => utc.with_timezone(&Local).format(pattern).to_string(),
+        }
+    }
+}
@@ -0,0 +1,59 @@
+use spider_client::message::Message;
+
+/// Running totals of the bytes this client has sent and received, for
+/// bandwidth analysis during load tests.
+///
+/// The channel does not report wire sizes, so each message is measured by
+/// its CBOR encoding. Framing and transport overhead are not counted.
+#[derive(Default)]
+pub struct Traffic {
+    sent: u64,
+    received: u64,
+    /// Whether the totals changed since they were last shown.
+    changed: bool,
+}
+
+impl Traffic {
+    pub fn record_sent(&mut self, msg: &Message) {
+        self.sent += size(msg);
+        self.changed = true;
+    }
+
+    pub fn record_received(&mut self, msg: &Message) {
+        self.received += size(msg);
+        self.changed = true;
+    }
+
+    /// Whether the totals changed since the last call.
+    pub fn take_changed(&mut self) -> bool {
+        std::mem::take(&mut self.changed)
+    }
+
+    pub fn render(&self) -> String {
+        format!(
+            "sent {}, received {}",
+            human(self.sent),
+            human(self.received)
+        )
+    }
+}
+
+fn size(msg: &Message) -> u64 {
+    serde_cbor::to_vec(msg)
+        .map(|bytes| bytes.len() as u64)
+        .unwrap_or_default()
+}
+
+fn human(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
@@ -0,0 +1,107 @@
+use serde_json::json;
+use spider_client::message::DatasetData;
+
+use crate::encoding::Encoding;
+
+/// One named step of a [`Pipeline`].
+#[derive(Clone)]
+pub enum Step {
+    Uppercase,
+    /// Replace the text with a JSON object holding it under `text`.
+    JsonWrap,
+    Prefix(String),
+    Base64,
+}
+
+impl Step {
+    /// Parse one step. Only the name is trimmed, so a prefix keeps any
+    /// trailing space.
+    fn parse(text: &str) -> Result<Self, String> {
+        match text.split_once(':') {
+            Some((name, prefix)) if name.trim() == "prefix" => Ok(Step::Prefix(prefix.to_string())),
+            None => match text.trim() {
+                "uppercase" => Ok(Step::Uppercase),
+                "json-wrap" => Ok(Step::JsonWrap),
+                "base64" => Ok(Step::Base64),
+                other => Err(format!("unknown transform: {}", other)),
+            },
+            Some((other, _)) => Err(format!("unknown transform: {}", other.trim())),
+        }
+    }
+
+    fn apply(&self, text: String) -> String {
+        match self {
+            Step::Uppercase => text.to_uppercase(),
+            Step::JsonWrap => json!({ "text": text }).to_string(),
+            Step::Prefix(prefix) => format!("{}{}", prefix, text),
+            Step::Base64 => Encoding::Base64.encode(text.as_bytes()).unwrap_or(text),
+        }
+    }
+}
+
+/// Transforms applied in order to message text, written as a comma
+/// separated list such as `uppercase,prefix:>> ,base64`.
+#[derive(Clone, Default)]
+pub struct Pipeline {
+    steps: Vec<Step>,
+}
+
+impl Pipeline {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let steps = text
+            .split(',')
+            .filter(|step| !step.trim().is_empty())
+            .map(Step::parse)
+            .collect::<Result<_, _>>()?;
+        Ok(Self { steps })
+    }
+
+    pub fn apply(&self, text: String) -> String {
+        self.steps.iter().fold(text, |text, step| step.apply(text))
+    }
+
+    /// Transform a payload's text: the payload itself if it is a string,
+    /// or its `text` field if it is a map. Other payloads are unchanged.
+    pub fn apply_data(&self, data: DatasetData) -> DatasetData {
+        if self.steps.is_empty() {
+            return data;
+        }
+        match data {
+            DatasetData::String(text) => DatasetData::String(self.apply(text)),
+            DatasetData::Map(mut map) => {
+                if let Some(DatasetData::String(text)) = map.get_mut("text") {
+                    *text = self.apply(std::mem::take(text));
+                }
+                DatasetData::Map(map)
+            }
+            data => data,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(pipeline: &str, text: &str) -> String {
+        Pipeline::parse(pipeline).unwrap().apply(text.to_string())
+    }
+
+    #[test]
+    fn chains_steps_in_order() {
+        assert_eq!(run("uppercase,base64", "hi"), "SEk=");
+        assert_eq!(run("base64,uppercase", "hi"), "AGK=");
+    }
+
+    #[test]
+    fn prefix_keeps_its_trailing_space() {
+        assert_eq!(run("prefix:>> ,uppercase", "hi"), ">> HI");
+        assert_eq!(run("uppercase, prefix:>> ", "hi"), ">> HI");
+    }
+
+    #[test]
+    fn rejects_unknown_steps() {
+        assert!(Pipeline::parse("uppercase,reverse").is_err());
+        assert!(Pipeline::parse("suffix:x").is_err());
+    }
+}
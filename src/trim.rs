@@ -0,0 +1,93 @@
+/// Decides how many of the oldest messages to delete to keep the Messages
+/// dataset at its cap.
+///
+/// Every dataset echo reports the full length, including deletes we have
+/// sent but the router has not applied yet. Deletes in flight are counted
+/// so a burst past the cap is trimmed in one go without over-deleting as
+/// the intermediate echoes arrive.
+pub struct Trimmer {
+    cap: usize,
+    in_flight: usize,
+    last_len: usize,
+}
+
+impl Trimmer {
+    pub fn new(cap: usize) -> Self {
+        Self {
+            cap,
+            in_flight: 0,
+            last_len: 0,
+        }
+    }
+
+    /// Record a new dataset length and return how many deletes to issue.
+    pub fn update(&mut self, len: usize) -> usize {
+        if len < self.last_len {
+            self.in_flight = self.in_flight.saturating_sub(self.last_len - len);
+        }
+        self.last_len = len;
+        if len <= self.cap {
+            self.in_flight = 0;
+            return 0;
+        }
+        let needed = (len - self.cap).saturating_sub(self.in_flight);
+        self.in_flight += needed;
+        needed
+    }
+
+    /// Deletes of the oldest entries sent but not yet reflected in the
+    /// latest length, so indices read from that update are this far ahead.
+    pub fn in_flight(&self) -> usize {
+        self.in_flight
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CAP: usize = 10;
+
+    /// Feed the trimmer the echoes of 50 appends that arrive before any
+    /// delete is applied, then the echoes of the deletes.
+    #[test]
+    fn settles_at_the_cap_after_a_burst() {
+        let mut trimmer = Trimmer::new(CAP);
+        let mut len = 0;
+        let mut pending = 0;
+        for _ in 0..50 {
+            len += 1;
+            pending += trimmer.update(len);
+        }
+        assert_eq!(pending, 50 - CAP);
+        while pending > 0 {
+            len -= 1;
+            pending -= 1;
+            assert_eq!(trimmer.update(len), 0);
+        }
+        assert_eq!(len, CAP);
+    }
+
+    /// Deletes applied between appends must not be issued twice.
+    #[test]
+    fn settles_at_the_cap_with_deletes_interleaved() {
+        let mut trimmer = Trimmer::new(CAP);
+        let mut len = 0;
+        let mut pending = 0;
+        for i in 0..50 {
+            len += 1;
+            pending += trimmer.update(len);
+            if i % 3 == 0 && pending > 0 {
+                len -= 1;
+                pending -= 1;
+                pending += trimmer.update(len);
+            }
+        }
+        while pending > 0 {
+            len -= 1;
+            pending -= 1;
+            pending += trimmer.update(len);
+        }
+        assert_eq!(len, CAP);
+    }
+}
@@ -0,0 +1,7 @@
+/// What one viewer has entered on the page, kept apart from other viewers
+/// so their inputs do not overwrite each other.
+#[derive(Default)]
+pub struct ViewerState {
+    /// Query entered in this viewer's Filter element, if any.
+    pub filter: Option<String>,
+}
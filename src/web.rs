@@ -0,0 +1,121 @@
+use std::{
+    fmt::Write,
+    net::IpAddr,
+    sync::{Arc, Mutex},
+};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+/// Seconds between browser refreshes of the mirror page.
+const REFRESH_SECS: u32 = 2;
+
+#[derive(Default)]
+struct Snapshot {
+    recps: Vec<String>,
+    msgs: Vec<String>,
+    traffic: String,
+}
+
+/// A read-only HTML view of the recipient and message lists, served over
+/// HTTP for browsers without a spider viewer.
+///
+/// The main loop pushes new lists as the datasets change; the server task
+/// only ever reads the latest copy.
+#[derive(Clone)]
+pub struct Mirror {
+    snapshot: Arc<Mutex<Snapshot>>,
+}
+
+impl Mirror {
+    /// Listen on `port` at `addr` and serve the page from a background
+    /// task.
+    pub async fn serve(addr: IpAddr, port: u16) -> Result<Self, std::io::Error> {
+        let listener = TcpListener::bind((addr, port)).await?;
+        let mirror = Self {
+            snapshot: Arc::default(),
+        };
+        let served = mirror.clone();
+        tokio::spawn(async move {
+            loop {
+                match listener.accept().await {
+                    Ok((stream, _)) => {
+                        let page = served.render();
+                        tokio::spawn(respond(stream, page));
+                    }
+                    Err(e) => log!(Error; "Web mirror stopped accepting: {}", e),
+                }
+            }
+        });
+        log!("Web mirror listening on {}:{}", addr, port);
+        Ok(mirror)
+    }
+
+    pub fn set_recps(&self, recps: Vec<String>) {
+        self.snapshot.lock().expect("mirror lock poisoned").recps = recps;
+    }
+
+    pub fn set_msgs(&self, msgs: Vec<String>) {
+        self.snapshot.lock().expect("mirror lock poisoned").msgs = msgs;
+    }
+
+    pub fn set_traffic(&self, traffic: String) {
+        self.snapshot.lock().expect("mirror lock poisoned").traffic = traffic;
+    }
+
+    fn render(&self) -> String {
+        let snapshot = self.snapshot.lock().expect("mirror lock poisoned");
+        let mut html = format!(
+            "<!DOCTYPE html><html><head><meta charset=\"utf-8\">\
+             <meta http-equiv=\"refresh\" content=\"{}\">\
+             <title>Test Router</title></head><body>",
+            REFRESH_SECS
+        );
+        if !snapshot.traffic.is_empty() {
+            let _ = write!(html, "<p>Traffic: {}</p>", escape(&snapshot.traffic));
+        }
+        render_list(&mut html, "Recipients", &snapshot.recps);
+        render_list(&mut html, "Messages", &snapshot.msgs);
+        html.push_str("</body></html>");
+        html
+    }
+}
+
+fn render_list(html: &mut String, title: &str, items: &[String]) {
+    let _ = write!(html, "<h2>{} ({})</h2><ul>", title, items.len());
+    for item in items {
+        let _ = write!(html, "<li>{}</li>", escape(item));
+    }
+    html.push_str("</ul>");
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Answer any request with the page; the path and headers are ignored.
+async fn respond(mut stream: TcpStream, page: String) {
+    let mut request = [0; 1024];
+    if stream.read(&mut request).await.is_err() {
+        return;
+    }
+    let response = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\n\
+         Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+        page.len(),
+        page
+    );
+    let _ = stream.write_all(response.as_bytes()).await;
+}